@@ -0,0 +1,15 @@
+(* A generator performs `yield` for each value it produces. The handler
+   decides what to do with the values and whether to resume the generator.
+*)
+let rec naturals = fun n -> perform yield n; naturals (n + 1) in
+let sum_first = fun n gen ->
+    let total = ref 0 in
+    let count = ref 0 in
+    handle gen () with
+    | return () -> !total
+    | yield x k ->
+        total := !total + x;
+        count := !count + 1;
+        if !count < n then k () else !total
+in
+sum_first 10 (fun () -> naturals 1)
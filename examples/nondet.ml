@@ -0,0 +1,17 @@
+(* The effect `choose` returns a boolean. The handler resumes the
+   continuation with both `true` and `false` and counts the successful runs.
+*)
+let count = fun comp ->
+    handle comp () with
+    | choose () k -> k true + k false
+    | return ok -> if ok then 1 else 0
+in
+let rec pick = fun lo hi ->
+    if lo < hi && perform choose () then pick (lo + 1) hi else lo
+in
+(* The number of Pythagorean triples with sides up to 20. *)
+count (fun () ->
+    let a = pick 1 20 in
+    let b = pick a 20 in
+    let c = pick b 20 in
+    a * a + b * b == c * c)
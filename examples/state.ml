@@ -0,0 +1,17 @@
+(* The effects `get` and `put` implement mutable state without references.
+   The handler turns the computation into a function of the initial state.
+*)
+let run = fun init comp ->
+    (handle comp () with
+    | return x -> fun s -> x
+    | get () k -> fun s -> k s s
+    | put s k -> fun t -> k () s) init
+in
+let rec sum_to = fun n ->
+    if n > 0 then
+        perform put (perform get () + n);
+        sum_to (n - 1)
+    else
+        perform get ()
+in
+run 0 (fun () -> sum_to 10)
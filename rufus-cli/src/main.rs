@@ -0,0 +1,297 @@
+use std::io::Read;
+use std::path::PathBuf;
+
+use clap::{ArgEnum, Parser};
+use serde_json::json;
+
+use rufus_core::diagnostics::Diagnostic;
+use rufus_core::humanizer::{ColumnUnit, Humanizer};
+use rufus_core::prelude::Prelude;
+use rufus_core::{cek, parse};
+
+/// Run a rufus program and print its result.
+#[derive(Parser)]
+#[clap(name = "rufus", version)]
+struct Options {
+    /// The program to run. Reads the program from stdin when omitted or `-`.
+    file: Option<PathBuf>,
+
+    /// Abort after this many machine transitions.
+    #[clap(long)]
+    max_steps: Option<u64>,
+
+    /// Abort when the continuation stack grows deeper than this.
+    #[clap(long)]
+    max_stack: Option<usize>,
+
+    /// How to print the result or the error.
+    #[clap(long, arg_enum, default_value = "text")]
+    format: Format,
+
+    /// Do not bring the bindings of the prelude into scope.
+    #[clap(long)]
+    no_prelude: bool,
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum Format {
+    Text,
+    Debug,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ErrorKind {
+    Parse,
+    Index,
+    Runtime,
+}
+
+#[derive(Debug)]
+struct Error {
+    kind: ErrorKind,
+    /// All parse errors are reported together, all other kinds of errors
+    /// have exactly one diagnostic.
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn main() {
+    let options = Options::parse();
+    let (name, source) = match read_source(&options.file) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let limits = cek::Limits {
+        max_steps: options.max_steps,
+        max_kont: options.max_stack,
+    };
+    let result = run(&source, limits, !options.no_prelude, options.format);
+    match options.format {
+        Format::Text | Format::Debug => match &result {
+            Ok(value) => println!("{}", value),
+            Err(err) => {
+                let rendered: Vec<_> = err
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic.render(&source, Some(&name)))
+                    .collect();
+                eprintln!("{}", rendered.join("\n\n"));
+            }
+        },
+        Format::Json => {
+            let json = match &result {
+                Ok(value) => json!({ "status": "ok", "value": value }),
+                Err(err) => {
+                    let humanizer = Humanizer::new(&source);
+                    let diagnostics: Vec<_> = err
+                        .diagnostics
+                        .iter()
+                        .map(|diagnostic| {
+                            let location = diagnostic.primary_span().map(|span| {
+                                let loc = humanizer.locate(span.start, ColumnUnit::Char);
+                                json!({ "line": loc.line + 1, "column": loc.column + 1 })
+                            });
+                            json!({
+                                "location": location,
+                                "message": diagnostic.message,
+                                "notes": diagnostic.notes,
+                            })
+                        })
+                        .collect();
+                    json!({
+                        "status": "error",
+                        "kind": err.kind.to_string(),
+                        "diagnostics": diagnostics,
+                    })
+                }
+            };
+            println!("{}", json);
+        }
+    }
+    if result.is_err() {
+        std::process::exit(1);
+    }
+}
+
+/// Read the program from the given file or from stdin. Returns the name
+/// to use in diagnostics together with the program.
+fn read_source(file: &Option<PathBuf>) -> std::io::Result<(String, String)> {
+    match file {
+        Some(path) if path.as_os_str() != "-" => {
+            let source = std::fs::read_to_string(path)?;
+            Ok((path.display().to_string(), source))
+        }
+        _ => {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source)?;
+            Ok(("<stdin>".to_string(), source))
+        }
+    }
+}
+
+/// Parse, index and evaluate a program, optionally in the scope of the
+/// prelude. Returns the value rendered according to the output format.
+fn run(source: &str, limits: cek::Limits, prelude: bool, format: Format) -> Result<String, Error> {
+    let prelude = if prelude { Some(Prelude::get()) } else { None };
+    let names = prelude.map_or(&[][..], Prelude::names);
+    let expr = parse::expr(source)
+        .into_result()
+        .map_err(|diagnostics| Error {
+            kind: ErrorKind::Parse,
+            diagnostics,
+        })?
+        .index_in(names)
+        .map_err(|err| Error {
+            kind: ErrorKind::Index,
+            diagnostics: vec![err.into()],
+        })?;
+    let (env, store) = prelude.map_or_else(Default::default, Prelude::scope);
+    let mut machine = cek::Machine::new(&expr)
+        .with_env(env)
+        .with_store(store)
+        .with_limits(limits);
+    let value = machine.run().map_err(|message| Error {
+        kind: ErrorKind::Runtime,
+        diagnostics: vec![Diagnostic::from_runtime_error(
+            message,
+            machine.error_span(),
+        )],
+    })?;
+    match format {
+        Format::Text | Format::Json => Ok(value.to_string()),
+        Format::Debug => Ok(format!("{:?}", value)),
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ErrorKind::Parse => "parse",
+            ErrorKind::Index => "index",
+            ErrorKind::Runtime => "runtime",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rufus_core::syntax::Span;
+
+    fn run_text(source: &str) -> Result<String, Error> {
+        run(source, cek::Limits::default(), true, Format::Text)
+    }
+
+    fn location(source: &str, err: &Error) -> String {
+        let span = err.diagnostics[0].primary_span().unwrap();
+        let humanizer = Humanizer::new(source);
+        humanizer.locate(span.start, ColumnUnit::Char).to_string()
+    }
+
+    #[test]
+    fn ok() {
+        assert_eq!(run_text("let x = 20 in 2 * x + 2").unwrap(), "42");
+    }
+
+    #[test]
+    fn example() {
+        let source = std::fs::read_to_string("../examples/fib.ml").unwrap();
+        assert_eq!(run_text(&source).unwrap(), "55");
+    }
+
+    #[test]
+    fn parse_error_located() {
+        let source = "let x = 1 in\nx +";
+        let err = run_text(source).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Parse);
+        assert_eq!(location(source, &err), "2:4");
+        assert_eq!(err.diagnostics[0].message, "unexpected end of input");
+    }
+
+    #[test]
+    fn parse_errors_reported_together() {
+        let err = run_text("let x = (1 +) in\nlet y = { a = } in\nx").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Parse);
+        let messages: Vec<_> = err.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["unexpected token `)`", "unexpected token `}`"]
+        );
+    }
+
+    #[test]
+    fn index_error_located() {
+        let source = "let x = 1 in\n  x + y";
+        let err = run_text(source).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Index);
+        assert_eq!(location(source, &err), "2:7");
+        assert_eq!(err.diagnostics[0].message, "unbound variable: y");
+        let expected = "\
+error: unbound variable: y
+ --> test.ml:2:7
+  |
+2 |   x + y
+  |       ^ not found in this scope";
+        assert_eq!(err.diagnostics[0].render(source, Some("test.ml")), expected);
+    }
+
+    #[test]
+    fn prelude() {
+        assert_eq!(run_text("sum (map succ [1; 2; 3])").unwrap(), "9");
+        assert_eq!(run_text("let sum = 1 in sum").unwrap(), "1");
+        let limits = cek::Limits::default();
+        let err = run("sum [1]", limits, false, Format::Text).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Index);
+        assert_eq!(err.diagnostics[0].message, "unbound variable: sum");
+    }
+
+    #[test]
+    fn runtime_error() {
+        let err = run_text("1 + true").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!(err.diagnostics[0].primary_span(), Some(Span::new(2, 3)));
+        assert_eq!(
+            err.diagnostics[0].render("1 + true", None),
+            "error: expected i64, found Bool(true)\n --> 1:3\n  |\n1 | 1 + true\n  |   ^ raised here"
+        );
+    }
+
+    #[test]
+    fn runtime_error_in_prelude() {
+        let err = run_text("nth [] 0").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!(err.diagnostics[0].primary_span(), None);
+        assert_eq!(
+            err.diagnostics[0].render("nth [] 0", None),
+            "error: hd of empty list"
+        );
+
+        let source = "(* äöü äöü äöü äöü äöü äöü äöü äöü *)\nnth [] 0";
+        let err = run_text(source).unwrap_err();
+        assert_eq!(
+            err.diagnostics[0].render(source, None),
+            "error: hd of empty list"
+        );
+    }
+
+    #[test]
+    fn step_limit() {
+        let limits = cek::Limits {
+            max_steps: Some(10),
+            max_kont: None,
+        };
+        let err = run(
+            "let rec f = fun x -> f x in f 0",
+            limits,
+            true,
+            Format::Text,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Runtime);
+        assert_eq!(err.diagnostics[0].message, "step limit of 10 exceeded");
+    }
+}
@@ -8,9 +8,31 @@ use crate::syntax::*;
 pub enum Value<'a> {
     Num(i64),
     Bool(bool),
+    Unit,
     PAP(PAP<'a>),
     Record(HashMap<&'a Name, Rc<Value<'a>>>),
+    Tuple(Vec<Rc<Value<'a>>>),
+    List(LinkedList<'a>),
+    /// A reference cell, given by its location in the store.
+    Ref(usize),
+    /// A runtime fault, like division by zero, raised as an exception.
+    Error(String),
     Fix(Rc<Value<'a>>),
+    /// A continuation captured by `callcc`, which can be thrown to.
+    Cont(Rc<Continuation<'a>>),
+}
+
+/// An immutable singly linked list. Lists share their tails such that `::`,
+/// `hd` and `tl` take constant time.
+#[derive(Clone, Default)]
+pub struct LinkedList<'a> {
+    node: Option<Rc<Node<'a>>>,
+}
+
+#[derive(Debug)]
+struct Node<'a> {
+    head: Rc<Value<'a>>,
+    tail: LinkedList<'a>,
 }
 
 #[derive(Clone, Debug)]
@@ -23,9 +45,30 @@ pub struct PAP<'a> {
 #[derive(Clone, Debug)]
 pub enum Prim<'a> {
     Builtin(OpCode),
-    Lam(&'a Expr, Rc<Env<'a>>),
+    Lam(&'a [Pattern], &'a Expr, Rc<Env<'a>>),
+    /// A lambda in the bindings of a `let rec`. Its environment contains the
+    /// recursive bindings themselves and is in the store at the given index.
+    Rec(&'a [Pattern], &'a Expr, usize),
     Record(&'a Vec<Name>),
+    /// Change the given fields of the record in the first argument.
+    Update(&'a Vec<Name>),
+    /// Add the given fields to the record in the first argument.
+    Extend(&'a Vec<Name>),
+    Restrict(&'a Vec<Name>),
     Proj(&'a Name),
+    Tuple,
+    TupleProj(usize),
+    List,
+    /// Resume a continuation captured by `perform` or `shift`.
+    Resume(Rc<Continuation<'a>>),
+}
+
+/// A continuation or a slice of it, together with the environment at the
+/// point where it was captured.
+#[derive(Debug)]
+pub struct Continuation<'a> {
+    env: Env<'a>,
+    kont: Vec<Kont<'a>>,
 }
 
 #[derive(Debug)]
@@ -33,23 +76,62 @@ enum Ctrl<'a> {
     Evaluating,
     Expr(&'a Expr),
     Value(Rc<Value<'a>>),
+    /// An exception propagating to the closest handler.
+    Raise(Rc<Value<'a>>),
+    /// An error no handler can catch.
     Error(String),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Env<'a> {
     stack: Vec<Rc<Value<'a>>>,
 }
 
-#[derive(Debug)]
+/// The contents of all reference cells and the environments of the lambdas
+/// bound by `let rec`.
+// NOTE(MH): Keeping the environments of `let rec`s here rather than in their
+// lambdas avoids reference cycles, which would never be freed.
+// TODO(MH): Cells and environments are never freed before the store is. Add
+// a garbage collector once programs allocate enough of them for this to
+// matter.
+#[derive(Clone, Debug, Default)]
+pub struct Store<'a> {
+    cells: Vec<Rc<Value<'a>>>,
+    rec_envs: Vec<Rc<Env<'a>>>,
+}
+
+#[derive(Clone, Debug)]
 enum Kont<'a> {
     Dump(Env<'a>),
     Pop(usize),
-    Arg(&'a Expr),
+    /// An argument to evaluate, with the application if it comes from an
+    /// `Expr::App`.
+    Arg(&'a Expr, Option<&'a Expr>),
     ArgValue(Rc<Value<'a>>),
-    App(Rc<Value<'a>>),
-    Let(&'a Name, &'a Expr),
+    App(Rc<Value<'a>>, Option<&'a Expr>),
+    Let(&'a Pattern, &'a Expr),
     If(&'a Expr, &'a Expr),
+    And(&'a Expr),
+    Or(&'a Expr),
+    Seq(&'a Expr),
+    Handler(&'a Pattern, &'a Expr),
+    /// The clauses of an effect handler.
+    Handle(&'a [Clause]),
+    Perform(&'a Name),
+    /// The delimiter of the continuations captured by `shift`.
+    Reset,
+    /// Check that the right operand of `&&` or `||` is a bool.
+    Bool,
+}
+
+/// Bounds on the resources a single run of the machine may use. A bound of
+/// `None` means the resource is unlimited.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Maximum number of machine transitions.
+    pub max_steps: Option<u64>,
+    /// Maximum depth of the continuation stack.
+    pub max_kont: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -57,6 +139,13 @@ pub struct Machine<'a> {
     ctrl: Ctrl<'a>,
     env: Env<'a>,
     kont: Vec<Kont<'a>>,
+    store: Store<'a>,
+    limits: Limits,
+    steps: u64,
+    /// The expression the machine was created for.
+    root: &'a Expr,
+    /// The application which raised the propagating exception.
+    error_app: Option<&'a Expr>,
 }
 
 impl<'a> Value<'a> {
@@ -76,6 +165,44 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// A description of the value's runtime type, e.g., `{ x : int; f : fun/1 }`.
+    pub fn type_name(&self) -> String {
+        use Value::*;
+        match self {
+            Num(_) => "int".to_string(),
+            Bool(_) => "bool".to_string(),
+            Unit => "unit".to_string(),
+            PAP(pap) => format!("fun/{}", pap.arity - pap.args.len()),
+            Fix(_) => "fun/1".to_string(),
+            Cont(_) => "cont".to_string(),
+            Ref(_) => "ref".to_string(),
+            Error(_) => "error".to_string(),
+            Tuple(values) => {
+                let types: Vec<_> = values.iter().map(|value| value.type_name()).collect();
+                format!("({})", types.join(" * "))
+            }
+            // NOTE(MH): Lists may contain values of different types. We only
+            // describe the first one.
+            List(list) => match list.uncons() {
+                Some((head, _)) => format!("{} list", head.type_name()),
+                None => "list".to_string(),
+            },
+            Record(record) => {
+                if record.is_empty() {
+                    "{}".to_string()
+                } else {
+                    let mut fields: Vec<_> = record.iter().collect();
+                    fields.sort_by_key(|(field, _)| *field);
+                    let fields: Vec<_> = fields
+                        .into_iter()
+                        .map(|(field, value)| format!("{} : {}", field, value.type_name()))
+                        .collect();
+                    format!("{{ {} }}", fields.join("; "))
+                }
+            }
+        }
+    }
+
     fn as_record(&self) -> Result<&HashMap<&'a Name, Rc<Value<'a>>>, String> {
         if let Value::Record(assigns) = self {
             Ok(assigns)
@@ -83,6 +210,89 @@ impl<'a> Value<'a> {
             Err(format!("expected record, found {:?}", self))
         }
     }
+
+    fn as_loc(&self) -> Result<usize, String> {
+        if let Value::Ref(loc) = self {
+            Ok(*loc)
+        } else {
+            Err(format!("expected ref, found {:?}", self))
+        }
+    }
+
+    fn as_cont(&self) -> Result<&Rc<Continuation<'a>>, String> {
+        if let Value::Cont(cont) = self {
+            Ok(cont)
+        } else {
+            Err(format!("expected continuation, found {:?}", self))
+        }
+    }
+
+    fn as_tuple(&self) -> Result<&[Rc<Value<'a>>], String> {
+        if let Value::Tuple(values) = self {
+            Ok(values)
+        } else {
+            Err(format!("expected tuple, found {:?}", self))
+        }
+    }
+
+    fn as_list(&self) -> Result<&LinkedList<'a>, String> {
+        if let Value::List(list) = self {
+            Ok(list)
+        } else {
+            Err(format!("expected list, found {:?}", self))
+        }
+    }
+}
+
+impl<'a> LinkedList<'a> {
+    pub fn cons(head: Rc<Value<'a>>, tail: Self) -> Self {
+        Self {
+            node: Some(Rc::new(Node { head, tail })),
+        }
+    }
+
+    /// Split a non-empty list into its head and tail.
+    pub fn uncons(&self) -> Option<(&Rc<Value<'a>>, &Self)> {
+        self.node.as_ref().map(|node| (&node.head, &node.tail))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.node.is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rc<Value<'a>>> {
+        std::iter::successors(self.uncons(), |(_, tail)| tail.uncons()).map(|(head, _)| head)
+    }
+}
+
+impl<'a> FromIterator<Rc<Value<'a>>> for LinkedList<'a> {
+    fn from_iter<I: IntoIterator<Item = Rc<Value<'a>>>>(iter: I) -> Self {
+        let values: Vec<_> = iter.into_iter().collect();
+        values
+            .into_iter()
+            .rev()
+            .fold(Self::default(), |tail, head| Self::cons(head, tail))
+    }
+}
+
+// NOTE(MH): The derived implementations of `Drop` and `Debug` would recurse
+// once per element and overflow the stack for long lists.
+impl<'a> Drop for LinkedList<'a> {
+    fn drop(&mut self) {
+        let mut next = self.node.take();
+        while let Some(node) = next {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => next = node.tail.node.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Debug for LinkedList<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
 }
 
 impl<'a> Ctrl<'a> {
@@ -90,6 +300,10 @@ impl<'a> Ctrl<'a> {
         Ctrl::Value(Rc::new(v))
     }
 
+    fn fault(message: String) -> Self {
+        Ctrl::Raise(Rc::new(Value::Error(message)))
+    }
+
     fn from_prim(prim: Prim<'a>, arity: usize) -> Self {
         assert!(arity > 0);
         Self::from_value(Value::PAP(PAP {
@@ -101,8 +315,8 @@ impl<'a> Ctrl<'a> {
 }
 
 impl<'a> Env<'a> {
-    fn new() -> Self {
-        Env { stack: Vec::new() }
+    pub fn new() -> Self {
+        Self::default()
     }
 
     fn get(&self, index: usize) -> &Rc<Value<'a>> {
@@ -116,7 +330,7 @@ impl<'a> Env<'a> {
     }
 
     pub fn push_many(&mut self, args: Vec<Rc<Value<'a>>>) {
-        self.stack.extend(args.into_iter());
+        self.stack.extend(args);
     }
 
     pub fn pop_many(&mut self, count: usize) {
@@ -125,13 +339,109 @@ impl<'a> Env<'a> {
     }
 }
 
+impl<'a> Store<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of cells allocated so far.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Get the contents of the cell at the given location.
+    pub fn get(&self, loc: usize) -> &Rc<Value<'a>> {
+        self.cells.get(loc).expect("bad store location")
+    }
+
+    fn alloc(&mut self, value: Rc<Value<'a>>) -> usize {
+        self.cells.push(value);
+        self.cells.len() - 1
+    }
+
+    fn set(&mut self, loc: usize, value: Rc<Value<'a>>) {
+        *self.cells.get_mut(loc).expect("bad store location") = value;
+    }
+
+    /// The index the next `let rec` environment will be allocated at.
+    fn next_rec_env(&self) -> usize {
+        self.rec_envs.len()
+    }
+
+    fn alloc_rec_env(&mut self, env: Env<'a>) -> usize {
+        self.rec_envs.push(Rc::new(env));
+        self.rec_envs.len() - 1
+    }
+
+    fn rec_env(&self, index: usize) -> &Env<'a> {
+        self.rec_envs.get(index).expect("bad let rec environment")
+    }
+}
+
+impl<'a> Kont<'a> {
+    fn arg(expr: &'a Expr) -> Self {
+        Kont::Arg(expr, None)
+    }
+}
+
 impl<'a> Machine<'a> {
     pub fn new(expr: &'a Expr) -> Self {
         Machine {
             ctrl: Ctrl::Expr(expr),
             env: Env::new(),
             kont: Vec::new(),
+            store: Store::new(),
+            limits: Limits::default(),
+            steps: 0,
+            root: expr,
+            error_app: None,
+        }
+    }
+
+    /// Run the machine with the given store rather than an empty one, e.g.,
+    /// to evaluate in the store left behind by a previous run.
+    pub fn with_store(mut self, store: Store<'a>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn store(&self) -> &Store<'a> {
+        &self.store
+    }
+
+    /// Take the store out of the machine, leaving an empty one behind.
+    pub fn take_store(&mut self) -> Store<'a> {
+        std::mem::take(&mut self.store)
+    }
+
+    /// Run the machine in the given environment rather than the empty one.
+    pub fn with_env(mut self, env: Env<'a>) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Check that the machine has not exceeded its limits.
+    fn check_limits(&self) -> Result<(), String> {
+        if let Some(max_steps) = self.limits.max_steps {
+            if self.steps > max_steps {
+                return Err(format!("step limit of {} exceeded", max_steps));
+            }
+        }
+        if let Some(max_kont) = self.limits.max_kont {
+            if self.kont.len() > max_kont {
+                return Err(format!("stack limit of {} exceeded", max_kont));
+            }
         }
+        Ok(())
     }
 
     /// Step when the control contains an expression.
@@ -139,41 +449,113 @@ impl<'a> Machine<'a> {
         use Expr::*;
 
         match ctrl_expr {
-            Var(_, None) => panic!("unindexed variable"),
-            Var(_, Some(index)) => {
+            Var(_, None, _) => panic!("unindexed variable"),
+            Var(_, Some(index), _) => {
                 let v = self.env.get(*index);
                 Ctrl::Value(Rc::clone(v))
             }
             Num(n) => Ctrl::from_value(Value::Num(*n)),
             Bool(b) => Ctrl::from_value(Value::Bool(*b)),
+            Unit => Ctrl::from_value(Value::Unit),
             PrimOp(op) => Ctrl::from_prim(Prim::Builtin(*op), op.arity()),
-            App(fun, args) => {
-                self.kont.extend(args.iter().rev().map(Kont::Arg));
+            Error(_) => Ctrl::Error("cannot evaluate syntax error".to_string()),
+            App(fun, args, _) => {
+                let app = Some(ctrl_expr);
+                self.kont
+                    .extend(args.iter().rev().map(|arg| Kont::Arg(arg, app)));
                 Ctrl::Expr(fun)
             }
-            Lam(params, body) => {
-                Ctrl::from_prim(Prim::Lam(body, Rc::new(self.env.clone())), params.len())
-            }
-            Let(binder, bound, body) => {
-                self.kont.push(Kont::Let(binder, body));
+            Lam(params, body) => Ctrl::from_prim(
+                Prim::Lam(params, body, Rc::new(self.env.clone())),
+                params.len(),
+            ),
+            Let(pattern, bound, body) => {
+                self.kont.push(Kont::Let(pattern, body));
                 Ctrl::Expr(bound)
             }
+            LetRec(bindings, body) => {
+                let index = self.store.next_rec_env();
+                let values = match rec_values(bindings, index) {
+                    Ok(values) => values,
+                    Err(e) => return Ctrl::fault(e),
+                };
+                let mut env = self.env.clone();
+                env.push_many(values.clone());
+                assert_eq!(self.store.alloc_rec_env(env), index);
+                self.kont.push(Kont::Pop(values.len()));
+                self.env.push_many(values);
+                Ctrl::Expr(body)
+            }
             If(cond, then, elze) => {
                 self.kont.push(Kont::If(then, elze));
                 Ctrl::Expr(cond)
             }
+            And(lhs, rhs) => {
+                self.kont.push(Kont::And(rhs));
+                Ctrl::Expr(lhs)
+            }
+            Or(lhs, rhs) => {
+                self.kont.push(Kont::Or(rhs));
+                Ctrl::Expr(lhs)
+            }
+            Seq(first, second) => {
+                self.kont.push(Kont::Seq(second));
+                Ctrl::Expr(first)
+            }
+            Try(body, pattern, handler) => {
+                self.kont.push(Kont::Handler(pattern, handler));
+                Ctrl::Expr(body)
+            }
+            Handle(body, clauses) => {
+                self.kont.push(Kont::Handle(clauses));
+                Ctrl::Expr(body)
+            }
+            Perform(op, arg) => {
+                self.kont.push(Kont::Perform(op));
+                Ctrl::Expr(arg)
+            }
             Record(fields, exprs) => {
                 if fields.is_empty() {
                     Ctrl::from_value(Value::Record(HashMap::new()))
                 } else {
-                    self.kont.extend(exprs.iter().rev().map(Kont::Arg));
+                    self.kont.extend(exprs.iter().rev().map(Kont::arg));
                     Ctrl::from_prim(Prim::Record(fields), fields.len())
                 }
             }
+            Update(record, fields, exprs) => {
+                self.kont.extend(exprs.iter().rev().map(Kont::arg));
+                self.kont.push(Kont::arg(record));
+                Ctrl::from_prim(Prim::Update(fields), fields.len() + 1)
+            }
+            Extend(record, fields, exprs) => {
+                self.kont.extend(exprs.iter().rev().map(Kont::arg));
+                self.kont.push(Kont::arg(record));
+                Ctrl::from_prim(Prim::Extend(fields), fields.len() + 1)
+            }
+            Restrict(record, fields) => {
+                self.kont.push(Kont::arg(record));
+                Ctrl::from_prim(Prim::Restrict(fields), 1)
+            }
             Proj(record, field) => {
-                self.kont.push(Kont::Arg(record));
+                self.kont.push(Kont::arg(record));
                 Ctrl::from_prim(Prim::Proj(field), 1)
             }
+            Tuple(exprs) => {
+                self.kont.extend(exprs.iter().rev().map(Kont::arg));
+                Ctrl::from_prim(Prim::Tuple, exprs.len())
+            }
+            TupleProj(tuple, index) => {
+                self.kont.push(Kont::arg(tuple));
+                Ctrl::from_prim(Prim::TupleProj(*index), 1)
+            }
+            List(exprs) => {
+                if exprs.is_empty() {
+                    Ctrl::from_value(Value::List(LinkedList::default()))
+                } else {
+                    self.kont.extend(exprs.iter().rev().map(Kont::arg));
+                    Ctrl::from_prim(Prim::List, exprs.len())
+                }
+            }
         }
     }
 
@@ -181,11 +563,29 @@ impl<'a> Machine<'a> {
     fn enter_prim(&mut self, prim: Prim<'a>, args: Vec<Rc<Value<'a>>>) -> Ctrl<'a> {
         use Prim::*;
         match prim {
+            Builtin(op @ (OpCode::Ref | OpCode::Deref | OpCode::Assign)) => {
+                match self.eval_store_op(op, args) {
+                    Ok(v) => Ctrl::Value(v),
+                    Err(e) => Ctrl::fault(e),
+                }
+            }
+            Builtin(OpCode::Raise) => Ctrl::Raise(Rc::clone(&args[0])),
+            Builtin(op @ (OpCode::Hd | OpCode::Tl)) => match eval_list_op(op, &args[0]) {
+                Ok(v) => Ctrl::Value(v),
+                Err(e) => Ctrl::fault(e),
+            },
+            Builtin(op @ (OpCode::Callcc | OpCode::Throw | OpCode::Reset | OpCode::Shift)) => {
+                self.enter_control_op(op, args)
+            }
             Builtin(op) => match op.eval(args) {
                 Ok(v) => Ctrl::from_value(v),
-                Err(e) => Ctrl::Error(e),
+                Err(e) => Ctrl::fault(e),
             },
-            Lam(body, env) => {
+            Lam(params, body, env) => {
+                let args = match bind_all(params, args) {
+                    Ok(args) => args,
+                    Err(e) => return Ctrl::fault(e),
+                };
                 let mut new_env = match Rc::try_unwrap(env) {
                     Ok(env) => env,
                     Err(env) => env.as_ref().clone(),
@@ -195,22 +595,134 @@ impl<'a> Machine<'a> {
                 self.kont.push(Kont::Dump(old_env));
                 Ctrl::Expr(body)
             }
-            Record(names) => {
-                Ctrl::from_value(Value::Record(names.iter().zip(args.into_iter()).collect()))
+            Rec(params, body, index) => {
+                let args = match bind_all(params, args) {
+                    Ok(args) => args,
+                    Err(e) => return Ctrl::fault(e),
+                };
+                let mut new_env = self.store.rec_env(index).clone();
+                new_env.push_many(args);
+                let old_env = std::mem::replace(&mut self.env, new_env);
+                self.kont.push(Kont::Dump(old_env));
+                Ctrl::Expr(body)
             }
+            Record(names) => Ctrl::from_value(Value::Record(names.iter().zip(args).collect())),
+            Update(_) | Extend(_) | Restrict(_) => match update_record(prim, args) {
+                Ok(record) => Ctrl::from_value(Value::Record(record)),
+                Err(e) => Ctrl::fault(e),
+            },
             Proj(field) => match args[0].as_record() {
                 Ok(record) => {
                     if let Some(value) = record.get(field) {
                         Ctrl::Value(Rc::clone(value))
                     } else {
-                        Ctrl::Error(format!("unknown field in record: {}", field))
+                        Ctrl::fault(format!("unknown field in record: {}", field))
                     }
                 }
-                Err(msg) => Ctrl::Error(msg),
+                Err(msg) => Ctrl::fault(msg),
             },
+            Tuple => Ctrl::from_value(Value::Tuple(args)),
+            TupleProj(index) => match args[0].as_tuple() {
+                Ok(values) => match values.get(index) {
+                    Some(value) => Ctrl::Value(Rc::clone(value)),
+                    None => Ctrl::fault(format!(
+                        "tuple of size {} has no component {}",
+                        values.len(),
+                        index
+                    )),
+                },
+                Err(msg) => Ctrl::fault(msg),
+            },
+            List => Ctrl::from_value(Value::List(args.into_iter().collect())),
+            Resume(cont) => {
+                let old_env = std::mem::replace(&mut self.env, cont.env.clone());
+                self.kont.push(Kont::Dump(old_env));
+                self.kont.extend(cont.kont.iter().cloned());
+                Ctrl::Value(Rc::clone(&args[0]))
+            }
+        }
+    }
+
+    /// Evaluate a primitive operating on the store.
+    fn eval_store_op(
+        &mut self,
+        op: OpCode,
+        mut args: Vec<Rc<Value<'a>>>,
+    ) -> Result<Rc<Value<'a>>, String> {
+        match op {
+            OpCode::Ref => {
+                let loc = self.store.alloc(args.pop().unwrap());
+                Ok(Rc::new(Value::Ref(loc)))
+            }
+            OpCode::Deref => Ok(Rc::clone(self.store.get(args[0].as_loc()?))),
+            OpCode::Assign => {
+                let loc = args[0].as_loc()?;
+                self.store.set(loc, args.pop().unwrap());
+                Ok(Rc::new(Value::Unit))
+            }
+            _ => panic!("not a store operation: {:?}", op),
         }
     }
 
+    /// Enter a primitive manipulating the continuation.
+    fn enter_control_op(&mut self, op: OpCode, mut args: Vec<Rc<Value<'a>>>) -> Ctrl<'a> {
+        match op {
+            OpCode::Callcc => {
+                let cont = Continuation {
+                    env: self.env.clone(),
+                    kont: self.kont.clone(),
+                };
+                self.kont
+                    .push(Kont::ArgValue(Rc::new(Value::Cont(Rc::new(cont)))));
+                Ctrl::Value(args.pop().unwrap())
+            }
+            OpCode::Throw => match args[0].as_cont() {
+                Ok(cont) => {
+                    self.env = cont.env.clone();
+                    self.kont = cont.kont.clone();
+                    Ctrl::Value(args.pop().unwrap())
+                }
+                Err(e) => Ctrl::fault(e),
+            },
+            OpCode::Reset => {
+                self.kont.push(Kont::Reset);
+                self.kont.push(Kont::ArgValue(Rc::new(Value::Unit)));
+                Ctrl::Value(args.pop().unwrap())
+            }
+            OpCode::Shift => {
+                let reset = self
+                    .kont
+                    .iter()
+                    .rposition(|kont| matches!(kont, Kont::Reset));
+                let i = match reset {
+                    Some(i) => i,
+                    None => return Ctrl::fault("shift without enclosing reset".to_string()),
+                };
+                let cont = self.capture(i);
+                self.kont.push(Kont::Reset);
+                self.kont.push(Kont::ArgValue(Rc::new(resume(cont))));
+                Ctrl::Value(args.pop().unwrap())
+            }
+            _ => panic!("not a control operation: {:?}", op),
+        }
+    }
+
+    /// Capture the continuation from the given frame on and remove it from
+    /// the machine. The environment is restored to the one the remaining
+    /// continuation expects.
+    fn capture(&mut self, from: usize) -> Continuation<'a> {
+        let kont = self.kont.split_off(from);
+        let env = self.env.clone();
+        for kont in kont.iter().rev() {
+            match kont {
+                Kont::Dump(env) => self.env = env.clone(),
+                Kont::Pop(count) => self.env.pop_many(*count),
+                _ => {}
+            }
+        }
+        Continuation { env, kont }
+    }
+
     /// Apply an argument to a PAP. If it is the last argument, enter the
     /// primitive.
     fn pap_apply_arg(&mut self, mut pap: PAP<'a>, arg: Rc<Value<'a>>) -> Ctrl<'a> {
@@ -243,41 +755,161 @@ impl<'a> Machine<'a> {
                 self.env.pop_many(count);
                 Ctrl::Value(value)
             }
-            Arg(arg) => {
-                self.kont.push(App(value));
+            Arg(arg, app) => {
+                self.kont.push(App(value, app));
                 Ctrl::Expr(arg)
             }
             ArgValue(arg) => {
-                self.kont.push(App(value));
+                self.kont.push(App(value, None));
                 Ctrl::Value(arg)
             }
-            App(fun) => match Rc::try_unwrap(fun) {
-                Ok(fun) => match fun {
-                    Value::PAP(pap) => self.pap_apply_arg(pap, value),
-                    Value::Fix(fun) => self.fix_apply_arg(fun, value),
-                    _ => Ctrl::Error(format!("expected PAP, found {:?}", fun)),
-                },
-                Err(fun) => match &*fun {
-                    Value::PAP(pap) => self.pap_apply_arg(pap.clone(), value),
-                    Value::Fix(fun) => self.fix_apply_arg(Rc::clone(fun), value),
-                    _ => Ctrl::Error(format!("expected PAP, found {:?}", fun)),
-                },
-            },
-            Let(_name, body) => {
-                self.kont.push(Kont::Pop(1));
-                self.env.push(value);
-                Ctrl::Expr(body)
+            App(fun, app) => {
+                let ctrl = match Rc::try_unwrap(fun) {
+                    Ok(fun) => match fun {
+                        Value::PAP(pap) => self.pap_apply_arg(pap, value),
+                        Value::Fix(fun) => self.fix_apply_arg(fun, value),
+                        _ => Ctrl::fault(format!("expected PAP, found {:?}", fun)),
+                    },
+                    Err(fun) => match &*fun {
+                        Value::PAP(pap) => self.pap_apply_arg(pap.clone(), value),
+                        Value::Fix(fun) => self.fix_apply_arg(Rc::clone(fun), value),
+                        _ => Ctrl::fault(format!("expected PAP, found {:?}", fun)),
+                    },
+                };
+                if let Ctrl::Raise(_) = ctrl {
+                    self.error_app = app;
+                }
+                ctrl
             }
+            Let(pattern, body) => match bind(pattern, value) {
+                Ok(values) => {
+                    self.kont.push(Kont::Pop(values.len()));
+                    self.env.push_many(values);
+                    Ctrl::Expr(body)
+                }
+                Err(e) => Ctrl::fault(e),
+            },
             If(then, elze) => match value.as_bool() {
                 Ok(true) => Ctrl::Expr(then),
                 Ok(false) => Ctrl::Expr(elze),
-                Err(e) => Ctrl::Error(e),
+                Err(e) => Ctrl::fault(e),
+            },
+            And(rhs) => match value.as_bool() {
+                Ok(true) => {
+                    self.kont.push(Kont::Bool);
+                    Ctrl::Expr(rhs)
+                }
+                Ok(false) => Ctrl::Value(value),
+                Err(e) => Ctrl::fault(e),
             },
+            Or(rhs) => match value.as_bool() {
+                Ok(true) => Ctrl::Value(value),
+                Ok(false) => {
+                    self.kont.push(Kont::Bool);
+                    Ctrl::Expr(rhs)
+                }
+                Err(e) => Ctrl::fault(e),
+            },
+            Bool => match value.as_bool() {
+                Ok(_) => Ctrl::Value(value),
+                Err(e) => Ctrl::fault(e),
+            },
+            Seq(second) => Ctrl::Expr(second),
+            Handler(..) => Ctrl::Value(value),
+            Handle(clauses) => {
+                let clause = clauses.iter().find_map(|clause| match clause {
+                    Clause::Return(pattern, body) => Some((pattern, body)),
+                    Clause::Effect(..) => None,
+                });
+                match clause {
+                    None => Ctrl::Value(value),
+                    Some((pattern, body)) => match bind(pattern, value) {
+                        Ok(values) => {
+                            self.kont.push(Kont::Pop(values.len()));
+                            self.env.push_many(values);
+                            Ctrl::Expr(body)
+                        }
+                        Err(e) => Ctrl::fault(e),
+                    },
+                }
+            }
+            Perform(op) => self.perform(op, value),
+            Reset => Ctrl::Value(value),
         }
     }
 
-    /// Step through the machine until completion.
-    pub fn run(mut self) -> Result<Rc<Value<'a>>, String> {
+    /// Perform an effect by entering the clause of the closest handler
+    /// handling it. The continuation up to and including the handler's frame
+    /// is captured and bound in the clause.
+    fn perform(&mut self, op: &Name, arg: Rc<Value<'a>>) -> Ctrl<'a> {
+        let handler = self.kont.iter().enumerate().rev().find_map(|(i, kont)| {
+            let clauses: &'a [Clause] = match kont {
+                Kont::Handle(clauses) => clauses,
+                _ => return None,
+            };
+            clauses.iter().find_map(|clause| match clause {
+                Clause::Effect(name, pattern, _, body) if name == op => {
+                    let values = bind(pattern, Rc::clone(&arg)).ok()?;
+                    Some((i, values, body))
+                }
+                _ => None,
+            })
+        });
+        let (i, mut values, body) = match handler {
+            Some(handler) => handler,
+            None => return Ctrl::fault(format!("unhandled effect: {}", op)),
+        };
+        let cont = self.capture(i);
+        values.push(Rc::new(resume(cont)));
+        self.kont.push(Kont::Pop(values.len()));
+        self.env.push_many(values);
+        Ctrl::Expr(body)
+    }
+
+    /// Unwind the continuation to the closest handler whose pattern matches
+    /// the exception and enter it. Returns an error if there is no such
+    /// handler.
+    fn unwind(&mut self, exception: Rc<Value<'a>>) -> Result<Ctrl<'a>, String> {
+        while let Some(kont) = self.kont.pop() {
+            match kont {
+                Kont::Dump(env) => self.env = env,
+                Kont::Pop(count) => self.env.pop_many(count),
+                Kont::Handler(pattern, handler) => {
+                    if let Ok(values) = bind(pattern, Rc::clone(&exception)) {
+                        self.kont.push(Kont::Pop(values.len()));
+                        self.env.push_many(values);
+                        self.error_app = None;
+                        return Ok(Ctrl::Expr(handler));
+                    }
+                }
+                _ => {}
+            }
+        }
+        match &*exception {
+            Value::Error(message) => Err(message.clone()),
+            _ => Err(format!("uncaught exception: {}", exception)),
+        }
+    }
+
+    /// Number of transitions the machine has made so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The span of the application which raised the uncaught exception the
+    /// last run failed with, if it was raised by one. Applications outside
+    /// the expression the machine was created for, e.g., in the prelude or in
+    /// earlier REPL inputs, have no span since it points into another source.
+    pub fn error_span(&self) -> Option<Span> {
+        match self.error_app? {
+            app @ Expr::App(_, _, span) if contains(self.root, app) => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// Step through the machine until completion. The machine cannot be run
+    /// again afterwards but can still be inspected.
+    pub fn run(&mut self) -> Result<Rc<Value<'a>>, String> {
         use Ctrl::*;
         loop {
             let old_ctrl = std::mem::replace(&mut self.ctrl, Ctrl::Evaluating);
@@ -288,9 +920,15 @@ impl<'a> Machine<'a> {
                     None => return Ok(value),
                     Some(kont) => self.step_value(value, kont),
                 },
+                Raise(exception) => self.unwind(exception)?,
                 Error(e) => return Err(e),
             };
-            self.ctrl = new_ctrl
+            self.ctrl = new_ctrl;
+            self.steps += 1;
+            if let Err(e) = self.check_limits() {
+                self.error_app = None;
+                return Err(e);
+            }
         }
     }
 
@@ -308,24 +946,208 @@ impl<'a> Machine<'a> {
     //     }
 }
 
+/// Check whether `sub` is `expr` itself or one of its descendants.
+fn contains(expr: &Expr, sub: &Expr) -> bool {
+    std::ptr::eq(expr, sub) || expr.children().any(|child| contains(child, sub))
+}
+
+/// Copy the record in the first argument and change, add or remove fields
+/// depending on the primitive.
+fn update_record<'a>(
+    prim: Prim<'a>,
+    mut args: Vec<Rc<Value<'a>>>,
+) -> Result<HashMap<&'a Name, Rc<Value<'a>>>, String> {
+    let values = args.split_off(1);
+    let mut record = args[0].as_record()?.clone();
+    match prim {
+        Prim::Update(names) => {
+            for (name, value) in names.iter().zip(values) {
+                match record.get_mut(name) {
+                    Some(old_value) => *old_value = value,
+                    None => return Err(format!("unknown field in record: {}", name)),
+                }
+            }
+        }
+        Prim::Extend(names) => {
+            for (name, value) in names.iter().zip(values) {
+                if record.insert(name, value).is_some() {
+                    return Err(format!("field already in record: {}", name));
+                }
+            }
+        }
+        Prim::Restrict(names) => {
+            for name in names {
+                if record.remove(name).is_none() {
+                    return Err(format!("unknown field in record: {}", name));
+                }
+            }
+        }
+        _ => panic!("not a record update: {:?}", prim),
+    }
+    Ok(record)
+}
+
+/// The values of the bindings of a `let rec`. Their lambdas are closed over
+/// the environment at `index` in the store, which must be allocated with an
+/// environment containing these values afterwards.
+fn rec_values<'a>(
+    bindings: &'a [(Binder, Expr)],
+    index: usize,
+) -> Result<Vec<Rc<Value<'a>>>, String> {
+    bindings
+        .iter()
+        .map(|(f, expr)| match rec_value(expr, index) {
+            Some(value) => Ok(Rc::new(value)),
+            None => Err(format!("cannot define {} with let rec", f.name)),
+        })
+        .collect()
+}
+
+/// The value of a recursive value, see `Expr::is_rec_value`, or `None` if the
+/// expression is not one. The indexer rejects such expressions but the
+/// machine can also run expressions which have not been indexed.
+fn rec_value<'a>(expr: &'a Expr, index: usize) -> Option<Value<'a>> {
+    let values = |exprs: &'a [Expr]| -> Option<Vec<_>> {
+        exprs
+            .iter()
+            .map(|expr| rec_value(expr, index).map(Rc::new))
+            .collect()
+    };
+    let value = match expr {
+        Expr::Lam(params, body) => Value::PAP(PAP {
+            prim: Prim::Rec(params, body, index),
+            arity: params.len(),
+            args: Vec::with_capacity(params.len()),
+        }),
+        Expr::Num(n) => Value::Num(*n),
+        Expr::Bool(b) => Value::Bool(*b),
+        Expr::Unit => Value::Unit,
+        Expr::Record(fields, exprs) => Value::Record(fields.iter().zip(values(exprs)?).collect()),
+        Expr::Tuple(exprs) => Value::Tuple(values(exprs)?),
+        Expr::List(exprs) => Value::List(values(exprs)?.into_iter().collect()),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Evaluate `hd` or `tl`.
+fn eval_list_op<'a>(op: OpCode, list: &Value<'a>) -> Result<Rc<Value<'a>>, String> {
+    match (op, list.as_list()?.uncons()) {
+        (OpCode::Hd, Some((head, _))) => Ok(Rc::clone(head)),
+        (OpCode::Tl, Some((_, tail))) => Ok(Rc::new(Value::List(tail.clone()))),
+        (OpCode::Hd, None) => Err("hd of empty list".to_string()),
+        (OpCode::Tl, None) => Err("tl of empty list".to_string()),
+        _ => unreachable!("{:?} is not a list operation", op),
+    }
+}
+
+/// Turn a continuation into a function resuming it.
+fn resume(cont: Continuation) -> Value {
+    Value::PAP(PAP {
+        prim: Prim::Resume(Rc::new(cont)),
+        arity: 1,
+        args: Vec::new(),
+    })
+}
+
+/// Match a value against a pattern. Returns the values of the pattern's
+/// variables from left to right.
+pub fn bind<'a>(pattern: &Pattern, value: Rc<Value<'a>>) -> Result<Vec<Rc<Value<'a>>>, String> {
+    let mut values = Vec::new();
+    bind_aux(pattern, value, &mut values)?;
+    Ok(values)
+}
+
+/// Match the arguments of a lambda against its parameters.
+fn bind_all<'a>(
+    patterns: &[Pattern],
+    args: Vec<Rc<Value<'a>>>,
+) -> Result<Vec<Rc<Value<'a>>>, String> {
+    if patterns.iter().all(Pattern::is_var) {
+        return Ok(args);
+    }
+    let mut values = Vec::with_capacity(args.len());
+    for (pattern, arg) in patterns.iter().zip(args) {
+        bind_aux(pattern, arg, &mut values)?;
+    }
+    Ok(values)
+}
+
+fn bind_aux<'a>(
+    pattern: &Pattern,
+    value: Rc<Value<'a>>,
+    values: &mut Vec<Rc<Value<'a>>>,
+) -> Result<(), String> {
+    match pattern {
+        Pattern::Var(_) => values.push(value),
+        Pattern::Unit => {
+            if !matches!(*value, Value::Unit) {
+                return Err(format!("expected unit, found {:?}", value));
+            }
+        }
+        Pattern::Tuple(patterns) => {
+            let components = value.as_tuple()?;
+            if components.len() != patterns.len() {
+                return Err(format!(
+                    "expected tuple of size {}, found {:?}",
+                    patterns.len(),
+                    value
+                ));
+            }
+            for (pattern, component) in patterns.iter().zip(components) {
+                bind_aux(pattern, Rc::clone(component), values)?;
+            }
+        }
+        Pattern::Record(fields) => {
+            let record = value.as_record()?;
+            for (field, pattern) in fields {
+                match record.get(field) {
+                    Some(value) => bind_aux(pattern, Rc::clone(value), values)?,
+                    None => return Err(format!("unknown field in record: {}", field)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 impl OpCode {
     pub fn eval(self, args: Vec<Rc<Value>>) -> Result<Value, String> {
         use op_code::*;
-        use std::ops::{Add, Div, Mul, Sub};
         use OpCode::*;
 
         match self {
-            Add => eval_arith(i64::add, args),
-            Sub => eval_arith(i64::sub, args),
-            Mul => eval_arith(i64::mul, args),
-            Div => eval_arith(i64::div, args),
+            Add => eval_arith(|x, y| overflow(x.checked_add(y)), args),
+            Sub => eval_arith(|x, y| overflow(x.checked_sub(y)), args),
+            Mul => eval_arith(|x, y| overflow(x.checked_mul(y)), args),
+            Div => eval_arith(|x, y| overflow(nonzero(x, y)?.checked_div(y)), args),
+            Mod => eval_arith(|x, y| overflow(nonzero(x, y)?.checked_rem(y)), args),
+            Pow => eval_arith(pow, args),
+            Land => eval_arith(|x, y| Ok(x & y), args),
+            Lor => eval_arith(|x, y| Ok(x | y), args),
+            Lxor => eval_arith(|x, y| Ok(x ^ y), args),
+            Lsl => eval_arith(|x, y| Ok(x << shift(y)?), args),
+            Lsr => eval_arith(|x, y| Ok(((x as u64) >> shift(y)?) as i64), args),
+            Asr => eval_arith(|x, y| Ok(x >> shift(y)?), args),
+            Min => eval_arith(|x, y| Ok(x.min(y)), args),
+            Max => eval_arith(|x, y| Ok(x.max(y)), args),
             Equals => Ok(Value::Bool(eval_equals(args))),
             NotEq => Ok(Value::Bool(!eval_equals(args))),
             Less => eval_comp(i64::lt, args),
             LessEq => eval_comp(i64::le, args),
             Greater => eval_comp(i64::gt, args),
             GreaterEq => eval_comp(i64::ge, args),
+            Neg => Ok(Value::Num(overflow(args[0].as_i64()?.checked_neg())?)),
+            Abs => Ok(Value::Num(overflow(args[0].as_i64()?.checked_abs())?)),
+            Not => Ok(Value::Bool(!args[0].as_bool()?)),
             Fix => Ok(Value::Fix(Rc::clone(&args[0]))),
+            Cons => Ok(Value::List(LinkedList::cons(
+                Rc::clone(&args[0]),
+                args[1].as_list()?.clone(),
+            ))),
+            Ref | Deref | Assign | Raise | Callcc | Throw | Reset | Shift | Hd | Tl => {
+                panic!("{:?} is evaluated by the machine", self)
+            }
         }
     }
 }
@@ -333,13 +1155,50 @@ impl OpCode {
 mod op_code {
     use super::*;
 
-    pub fn eval_arith<F: FnOnce(i64, i64) -> i64>(
+    pub fn eval_arith<F: FnOnce(i64, i64) -> Result<i64, String>>(
         f: F,
         args: Vec<Rc<Value>>,
     ) -> Result<Value, String> {
         let x = args[0].as_i64()?;
         let y = args[1].as_i64()?;
-        Ok(Value::Num(f(x, y)))
+        Ok(Value::Num(f(x, y)?))
+    }
+
+    pub fn overflow(result: Option<i64>) -> Result<i64, String> {
+        result.ok_or_else(|| "integer overflow".to_string())
+    }
+
+    /// Check that the divisor `y` is not zero and pass the dividend through.
+    /// Division truncates towards zero and `x mod y` has the sign of `x`.
+    pub fn nonzero(x: i64, y: i64) -> Result<i64, String> {
+        if y == 0 {
+            Err("division by zero".to_string())
+        } else {
+            Ok(x)
+        }
+    }
+
+    pub fn pow(x: i64, y: i64) -> Result<i64, String> {
+        if y < 0 {
+            return Err(format!("negative exponent {}", y));
+        }
+        match u32::try_from(y) {
+            Ok(y) => overflow(x.checked_pow(y)),
+            Err(_) => match x {
+                0 | 1 => Ok(x),
+                -1 => Ok(if y % 2 == 0 { 1 } else { -1 }),
+                _ => overflow(None),
+            },
+        }
+    }
+
+    /// Check that a shift amount is within the bit width of `i64`. Bits
+    /// shifted out are lost.
+    pub fn shift(y: i64) -> Result<u32, String> {
+        match u32::try_from(y) {
+            Ok(y) if y < i64::BITS => Ok(y),
+            _ => Err(format!("shift amount {} out of range", y)),
+        }
     }
 
     pub fn eval_equals(args: Vec<Rc<Value>>) -> bool {
@@ -351,6 +1210,10 @@ mod op_code {
         match (&**x, &**y) {
             (Num(x), Num(y)) => x == y,
             (Bool(x), Bool(y)) => x == y,
+            (Unit, Unit) => true,
+            // NOTE(MH): References are equal if they are the same cell.
+            (Ref(x), Ref(y)) => x == y,
+            (Error(x), Error(y)) => x == y,
             (Record(x), Record(y)) => {
                 use std::collections::HashSet;
                 let x_keys = x.keys().collect::<HashSet<_>>();
@@ -365,6 +1228,19 @@ mod op_code {
                 }
                 true
             }
+            (Tuple(x), Tuple(y)) => {
+                x.len() == y.len() && x.iter().zip(y).all(|(x, y)| eval_equals2(x, y))
+            }
+            (List(x), List(y)) => {
+                let (mut x, mut y) = (x.iter(), y.iter());
+                loop {
+                    match (x.next(), y.next()) {
+                        (Some(x), Some(y)) if eval_equals2(x, y) => {}
+                        (None, None) => return true,
+                        _ => return false,
+                    }
+                }
+            }
             (_, _) => false,
         }
     }
@@ -385,6 +1261,7 @@ impl<'a> fmt::Display for Value<'a> {
         match self {
             Num(n) => write!(f, "{}", n),
             Bool(b) => write!(f, "{}", b),
+            Unit => write!(f, "()"),
             PAP(_) => write!(f, "<PAP>"),
             Record(record) => {
                 if record.is_empty() {
@@ -404,6 +1281,29 @@ impl<'a> fmt::Display for Value<'a> {
                 }
             }
             Fix(_) => write!(f, "<FIX>"),
+            Cont(_) => write!(f, "<cont>"),
+            Ref(loc) => write!(f, "<ref {}>", loc),
+            Error(message) => write!(f, "<error: {}>", message),
+            Tuple(values) => {
+                write!(f, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, ")")
+            }
+            List(list) => {
+                write!(f, "[")?;
+                for (i, value) in list.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
         }
     }
 }
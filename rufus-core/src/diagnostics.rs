@@ -0,0 +1,412 @@
+use std::fmt::{self, Display, Write};
+
+use lalrpop_util::ParseError;
+
+use crate::humanizer::{ColumnUnit, Humanizer};
+use crate::lexer::{LexicalError, LexicalErrorKind};
+use crate::syntax::{IndexError, IndexErrorKind, Span};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A span in the source together with a message explaining it. Primary labels
+/// point at the cause of a diagnostic, secondary labels provide context.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// The span of the first primary label, if any.
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .map(|label| label.span)
+    }
+
+    pub fn from_parse_error<T: Display>(err: ParseError<usize, T, LexicalError>) -> Self {
+        match err {
+            ParseError::InvalidToken { location } => Self::error("invalid token")
+                .with_primary(Span::new(location, location), "not a valid token"),
+            ParseError::UnrecognizedEOF { location, expected } => {
+                Self::error("unexpected end of input")
+                    .with_primary(Span::new(location, location), "input ends here")
+                    .with_expected(&expected)
+            }
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => Self::error(format!("unexpected token `{}`", token))
+                .with_primary(Span::new(start, end), "unexpected token")
+                .with_expected(&expected),
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => Self::error(format!("extra token `{}`", token))
+                .with_primary(Span::new(start, end), "expected end of input"),
+            ParseError::User { error } => error.into(),
+        }
+    }
+
+    /// A runtime error, located at the application which raised it if known.
+    pub fn from_runtime_error(message: impl Into<String>, span: Option<Span>) -> Self {
+        let diagnostic = Self::error(message);
+        match span {
+            Some(span) => diagnostic.with_primary(span, "raised here"),
+            None => diagnostic,
+        }
+    }
+
+    fn with_expected(self, expected: &[String]) -> Self {
+        match expected {
+            [] => self,
+            [token] => self.with_note(format!("expected {}", describe_token(token))),
+            _ => {
+                let expected: Vec<_> = expected.iter().map(|t| describe_token(t)).collect();
+                self.with_note(format!("expected one of {}", expected.join(", ")))
+            }
+        }
+    }
+
+    /// Render the diagnostic for the terminal. Every source line with a label
+    /// is shown with the labeled spans underlined, `^` for primary labels and
+    /// `-` for secondary ones. The location of the primary label is prefixed
+    /// with `name` if given.
+    pub fn render(&self, source: &str, name: Option<&str>) -> String {
+        let humanizer = Humanizer::new(source);
+        let mut out = format!("{}: {}\n", self.severity, self.message);
+
+        let mut labels: Vec<_> = self
+            .labels
+            .iter()
+            .map(|label| (humanizer.locate(label.span.start, ColumnUnit::Char), label))
+            .collect();
+        labels.sort_by_key(|(loc, label)| (loc.line, !label.primary, loc.column));
+        let gutter = labels
+            .iter()
+            .map(|(loc, _)| (loc.line + 1).to_string().len())
+            .max()
+            .unwrap_or(0);
+        let pad = " ".repeat(gutter);
+
+        if let Some(span) = self.primary_span() {
+            let loc = humanizer.locate(span.start, ColumnUnit::Char);
+            match name {
+                Some(name) => writeln!(out, "{}--> {}:{}", pad, name, loc),
+                None => writeln!(out, "{}--> {}", pad, loc),
+            }
+            .unwrap();
+        }
+        if !labels.is_empty() {
+            writeln!(out, "{} |", pad).unwrap();
+        }
+        let mut last_line = None;
+        for (loc, label) in &labels {
+            let line = humanizer.line(loc.line);
+            if last_line != Some(loc.line) {
+                writeln!(out, "{:>width$} | {}", loc.line + 1, line, width = gutter).unwrap();
+                last_line = Some(loc.line);
+            }
+            // NOTE(MH): Spans reaching beyond the end of the line are only
+            // underlined up to the end of the line. Columns are counted in
+            // chars so that spans which do not fall on char boundaries, e.g.,
+            // from another source, cannot make us slice into a char.
+            let len = line.chars().count();
+            let indent = (loc.column as usize).min(len);
+            let end = humanizer.locate(label.span.end, ColumnUnit::Char);
+            let end = if end.line == loc.line {
+                (end.column as usize).min(len)
+            } else {
+                len
+            };
+            let width = end.saturating_sub(indent).max(1);
+            let marker = if label.primary { "^" } else { "-" };
+            write!(
+                out,
+                "{} | {}{}",
+                pad,
+                " ".repeat(indent),
+                marker.repeat(width)
+            )
+            .unwrap();
+            if !label.message.is_empty() {
+                write!(out, " {}", label.message).unwrap();
+            }
+            out.push('\n');
+        }
+        for note in &self.notes {
+            writeln!(out, "{} = note: {}", pad, note).unwrap();
+        }
+        if let Some(help) = &self.help {
+            writeln!(out, "{} = help: {}", pad, help).unwrap();
+        }
+        out.pop();
+        out
+    }
+}
+
+/// Turn the name of a terminal in `parser.lalrpop` into a description.
+fn describe_token(token: &str) -> String {
+    match token {
+        "ID" => "identifier".to_string(),
+        "NUMBER" => "number".to_string(),
+        _ => format!("`{}`", token.trim_matches('"')),
+    }
+}
+
+impl From<LexicalError> for Diagnostic {
+    fn from(err: LexicalError) -> Self {
+        let diagnostic = Self::error(err.to_string());
+        match err.kind {
+            LexicalErrorKind::InvalidCharacter(_) => {
+                diagnostic.with_primary(err.span, "not part of the language")
+            }
+            LexicalErrorKind::UnterminatedComment => diagnostic
+                .with_primary(err.span, "comment starts here")
+                .with_note("comments nest, every `(*` needs a matching `*)`"),
+            LexicalErrorKind::UnterminatedString => {
+                diagnostic.with_primary(err.span, "string starts here")
+            }
+            LexicalErrorKind::NumberOutOfRange(_) => diagnostic
+                .with_primary(err.span, "number literal")
+                .with_note(format!("the largest number is {}", i64::MAX)),
+        }
+    }
+}
+
+impl From<IndexError> for Diagnostic {
+    fn from(err: IndexError) -> Self {
+        let label = match err.kind {
+            IndexErrorKind::UnboundVariable => "not found in this scope",
+            IndexErrorKind::InvalidLetRec => "not a function or record",
+            IndexErrorKind::DuplicateLetRec => "defined again here",
+        };
+        Self::error(err.message).with_primary(err.span, label)
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn parse_error(source: &str) -> Diagnostic {
+        parse::expr(source).into_result().unwrap_err().remove(0)
+    }
+
+    #[test]
+    fn test_render_index_error() {
+        let source = "let x = 1 in\nx + y";
+        let err = parse::expr(source)
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap_err();
+        let expected = "\
+error: unbound variable: y
+ --> test.ml:2:5
+  |
+2 | x + y
+  |     ^ not found in this scope";
+        assert_eq!(
+            Diagnostic::from(err).render(source, Some("test.ml")),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_render_invalid_let_rec() {
+        let source = "let rec x = x + 1 in x";
+        let err = parse::expr(source)
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap_err();
+        let expected = "\
+error: cannot define x with let rec: only functions and records, tuples and lists of them can be defined recursively
+ --> 1:9
+  |
+1 | let rec x = x + 1 in x
+  |         ^ not a function or record";
+        assert_eq!(Diagnostic::from(err).render(source, None), expected);
+    }
+
+    #[test]
+    fn test_render_parse_error() {
+        let source = "let x = in 1";
+        let expected = "\
+error: unexpected token `in`
+ --> 1:9
+  |
+1 | let x = in 1
+  |         ^^ unexpected token
+  = note: expected one of";
+        assert!(parse_error(source)
+            .render(source, None)
+            .starts_with(expected));
+    }
+
+    #[test]
+    fn test_render_lexical_error() {
+        let source = "let x = 1 in\n(* (* nested *)\nx";
+        let expected = "\
+error: unterminated comment
+ --> 2:1
+  |
+2 | (* (* nested *)
+  | ^^ comment starts here
+  = note: comments nest, every `(*` needs a matching `*)`";
+        assert_eq!(parse_error(source).render(source, None), expected);
+    }
+
+    #[test]
+    fn test_render_labels() {
+        let source = "let x = 1 in\nlet y = x +\n  true in\ny";
+        let diagnostic = Diagnostic::error("type mismatch")
+            .with_primary(Span::new(27, 31), "expected int")
+            .with_secondary(Span::new(23, 24), "because of this operator")
+            .with_secondary(Span::new(4, 5), "")
+            .with_help("convert the bool to an int");
+        let expected = "\
+error: type mismatch
+ --> 3:3
+  |
+1 | let x = 1 in
+  |     -
+2 | let y = x +
+  |           - because of this operator
+3 |   true in
+  |   ^^^^ expected int
+  = help: convert the bool to an int";
+        assert_eq!(diagnostic.render(source, None), expected);
+    }
+
+    #[test]
+    fn test_render_end_of_input() {
+        let source = "1 +";
+        let expected = "\
+error: unexpected end of input
+ --> 1:4
+  |
+1 | 1 +
+  |    ^ input ends here";
+        let rendered = parse_error(source).render(source, None);
+        assert_eq!(rendered.split("\n  = note").next().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_render_unicode() {
+        let source = "(* ä *) x\r\n  + y";
+        let err = parse::expr(source)
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap_err();
+        let expected = "\
+error: unbound variable: x
+ --> 1:9
+  |
+1 | (* ä *) x
+  |         ^ not found in this scope";
+        assert_eq!(Diagnostic::from(err).render(source, None), expected);
+    }
+
+    #[test]
+    fn test_render_off_char_boundaries() {
+        let source = "(* äöü *) x";
+        let diagnostic = Diagnostic::error("boom")
+            .with_primary(Span::new(4, 7), "inside")
+            .with_secondary(Span::new(13, 30), "past the end");
+        let expected = "\
+error: boom
+ --> 1:5
+  |
+1 | (* äöü *) x
+  |     ^ inside
+  |           - past the end";
+        assert_eq!(diagnostic.render(source, None), expected);
+    }
+
+    #[test]
+    fn test_render_without_labels() {
+        let diagnostic = Diagnostic::error("division by zero").with_note("in `1 / 0`");
+        assert_eq!(
+            diagnostic.render("1 / 0", None),
+            "error: division by zero\n = note: in `1 / 0`"
+        );
+    }
+}
@@ -5,42 +5,134 @@ pub struct SourceLocation {
     pub line: u32,
     pub column: u32,
 }
+
+/// The unit in which columns are counted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColumnUnit {
+    Byte,
+    Char,
+    /// UTF-16 code units, as used by the LSP and by JavaScript editors.
+    Utf16,
+}
+
+/// Translates byte offsets into the source to line/column locations and back.
+/// Lines can be terminated by `\n`, `\r\n` or `\r`.
 #[derive(Debug, Eq, PartialEq)]
 pub struct Humanizer {
+    source: String,
     line_starts: Vec<usize>,
 }
 
+impl ColumnUnit {
+    fn width(self, c: char) -> usize {
+        match self {
+            ColumnUnit::Byte => c.len_utf8(),
+            ColumnUnit::Char => 1,
+            ColumnUnit::Utf16 => c.len_utf16(),
+        }
+    }
+}
+
 impl Humanizer {
     pub fn new(input: &str) -> Self {
-        let mut line_starts = Vec::new();
+        let bytes = input.as_bytes();
+        let mut line_starts = vec![0];
         let mut index = 0;
-        line_starts.push(index);
-        for line in input.lines() {
-            // FIXME(MH): This assumes a newline character is just one byte,
-            // which is not true on Windows.
-            index += line.len() + 1;
-            line_starts.push(index);
+        while index < bytes.len() {
+            match bytes[index] {
+                b'\n' => line_starts.push(index + 1),
+                b'\r' => {
+                    if bytes.get(index + 1) == Some(&b'\n') {
+                        index += 1;
+                    }
+                    line_starts.push(index + 1);
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        // NOTE(MH): Like a terminated last line, an unterminated one is
+        // followed by the start of an empty line, as if it ended in `\n`.
+        if line_starts.last() != Some(&bytes.len()) {
+            line_starts.push(bytes.len() + 1);
+        }
+        Self {
+            source: input.to_string(),
+            line_starts,
         }
-        Self { line_starts }
     }
 
+    /// Translate a byte offset into a location with the column counted in
+    /// bytes.
     pub fn run(&self, loc: usize) -> SourceLocation {
+        self.locate(loc, ColumnUnit::Byte)
+    }
+
+    /// Translate a byte offset into a location with the column counted in the
+    /// given unit. Only byte columns can reach past the end of the source.
+    pub fn locate(&self, loc: usize, unit: ColumnUnit) -> SourceLocation {
         let line = self
             .line_starts
             .binary_search(&loc)
             .unwrap_or_else(|x| x - 1);
+        let start = self.line_starts[line];
+        let column = match unit {
+            ColumnUnit::Byte => loc - start,
+            _ => self.source[start.min(self.source.len())..]
+                .char_indices()
+                .take_while(|(i, _)| start + i < loc)
+                .map(|(_, c)| unit.width(c))
+                .sum::<usize>(),
+        };
         SourceLocation {
             line: line as u32,
-            column: (loc - self.line_starts[line]) as u32,
+            column: column as u32,
         }
     }
-}
 
-pub fn sanitize_source_span(msg: &mut String) {
-    let regex = regex::Regex::new(r"\d+:\d+(:)\d+:\d+").unwrap();
-    if let Some(caps) = regex.captures(msg) {
-        let range = caps.get(1).unwrap().range();
-        msg.replace_range(range, "-");
+    /// Translate a source location back into a byte offset.
+    pub fn offset(&self, loc: SourceLocation) -> usize {
+        self.offset_in(loc, ColumnUnit::Byte)
+    }
+
+    /// Translate a location with the column counted in the given unit back
+    /// into a byte offset. Byte columns are taken as they are. Other columns
+    /// past the end of the line are clamped to the end of the line, lines
+    /// past the end of the source to the end of the source.
+    pub fn offset_in(&self, loc: SourceLocation, unit: ColumnUnit) -> usize {
+        let line = loc.line as usize;
+        if unit == ColumnUnit::Byte {
+            let line = line.min(self.line_starts.len() - 1);
+            return self.line_starts[line] + loc.column as usize;
+        }
+        if line >= self.line_starts.len() {
+            return self.source.len();
+        }
+        let start = self.line_starts[line].min(self.source.len());
+        let text = self.line(loc.line);
+        let mut column = 0;
+        for (i, c) in text.char_indices() {
+            if column >= loc.column as usize {
+                return start + i;
+            }
+            column += unit.width(c);
+        }
+        start + text.len()
+    }
+
+    /// The text of a line without its terminator.
+    pub fn line(&self, line: u32) -> &str {
+        let line = line as usize;
+        let len = self.source.len();
+        let start = match self.line_starts.get(line) {
+            Some(start) => (*start).min(len),
+            None => return "",
+        };
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(len, |end| (*end).min(len));
+        self.source[start..end].trim_end_matches(&['\n', '\r'][..])
     }
 }
 
@@ -101,6 +193,69 @@ mod tests {
         ];
         for (loc, line, column) in cases {
             assert_eq!(humanizer.run(loc), SourceLocation { line, column });
+            assert_eq!(humanizer.offset(SourceLocation { line, column }), loc);
+        }
+    }
+
+    #[test]
+    fn test_crlf_line_starts() {
+        let cases = vec![
+            ("a\r\nb", vec![0, 3, 5]),
+            ("a\r\n", vec![0, 3]),
+            ("a\rb\r\n\nc", vec![0, 2, 5, 6, 8]),
+            ("\r\r\n", vec![0, 1, 3]),
+        ];
+        for (input, expected_line_starts) in cases {
+            let humanizer = Humanizer::new(input);
+            assert_eq!(humanizer.line_starts, expected_line_starts, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_crlf() {
+        let humanizer = Humanizer::new("ab\r\ncd\re\r\n");
+        let cases = vec![
+            (0, 0, 0),
+            (2, 0, 2),
+            (4, 1, 0),
+            (5, 1, 1),
+            (7, 2, 0),
+            (8, 2, 1),
+        ];
+        for (loc, line, column) in cases {
+            assert_eq!(humanizer.run(loc), SourceLocation { line, column });
+            assert_eq!(humanizer.offset(SourceLocation { line, column }), loc);
+        }
+        assert_eq!(humanizer.line(0), "ab");
+        assert_eq!(humanizer.line(1), "cd");
+        assert_eq!(humanizer.line(2), "e");
+        assert_eq!(humanizer.line(3), "");
+        // NOTE(MH): Columns past the end of the line do not spill into the
+        // next line.
+        let loc = SourceLocation { line: 0, column: 5 };
+        assert_eq!(humanizer.offset_in(loc, ColumnUnit::Char), 2);
+    }
+
+    #[test]
+    fn test_units() {
+        use ColumnUnit::*;
+        // NOTE(MH): `ä` takes two bytes in UTF-8 and one unit in UTF-16,
+        // `𝔘` takes four bytes in UTF-8 and two units in UTF-16.
+        let humanizer = Humanizer::new("x\n(* ä𝔘 *) y");
+        let y = "x\n(* ä𝔘 *) ".len();
+        let cases = vec![(Byte, 13), (Char, 9), (Utf16, 10)];
+        for (unit, column) in cases {
+            let loc = SourceLocation { line: 1, column };
+            assert_eq!(humanizer.locate(y, unit), loc);
+            assert_eq!(humanizer.offset_in(loc, unit), y);
         }
+        let a = "x\n(* ".len();
+        assert_eq!(humanizer.locate(a + 2, Char).column, 4);
+        assert_eq!(humanizer.locate(a + 2, Utf16).column, 4);
+        assert_eq!(humanizer.locate(a + 6, Utf16).column, 6);
+        assert_eq!(
+            humanizer.offset_in(SourceLocation { line: 1, column: 4 }, Utf16),
+            a + 2
+        );
     }
 }
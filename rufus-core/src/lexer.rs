@@ -0,0 +1,659 @@
+use std::fmt;
+
+use crate::syntax::Span;
+
+/// The keywords of the language. This must be kept in sync with the tokens
+/// declared in `parser.lalrpop`.
+pub const KEYWORDS: &[&str] = &[
+    "fun", "let", "rec", "in", "fix", "if", "then", "else", "true", "false", "not", "mod", "land",
+    "lor", "lxor", "lsl", "lsr", "asr", "abs", "min", "max", "ref", "raise", "try", "with",
+    "handle", "perform", "return", "callcc", "throw", "reset", "shift", "and", "without", "hd",
+    "tl",
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Token<'a> {
+    Fun,
+    Let,
+    Rec,
+    In,
+    Fix,
+    If,
+    Then,
+    Else,
+    True,
+    False,
+    Not,
+    Mod,
+    Land,
+    Lor,
+    Lxor,
+    Lsl,
+    Lsr,
+    Asr,
+    Abs,
+    Min,
+    Max,
+    Ref,
+    Raise,
+    Try,
+    With,
+    Handle,
+    Perform,
+    Return,
+    Callcc,
+    Throw,
+    Reset,
+    Shift,
+    And,
+    Without,
+    Hd,
+    Tl,
+    Ident(&'a str),
+    /// A number literal, which is negative if it starts with `-`.
+    Number(&'a str),
+    /// A string literal including its quotes. The language has no strings
+    /// yet, but lexing them gives better errors.
+    String(&'a str),
+    /// A comment including its delimiters. Only produced by a lexer created
+    /// with `Lexer::with_comments`.
+    Comment(&'a str),
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Dot,
+    Semi,
+    Comma,
+    Assign,
+    Arrow,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Equals,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    StarStar,
+    Bang,
+    ColonEq,
+    AndAnd,
+    OrOr,
+    Bar,
+    ColonColon,
+}
+
+/// A coarse classification of tokens, e.g., for syntax highlighting.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenClass {
+    Keyword,
+    Identifier,
+    Number,
+    String,
+    Comment,
+    Bracket,
+    Operator,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LexicalErrorKind {
+    InvalidCharacter(char),
+    UnterminatedComment,
+    UnterminatedString,
+    NumberOutOfRange(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LexicalError {
+    pub kind: LexicalErrorKind,
+    /// The span of the offending character, the opening delimiter of an
+    /// unterminated comment or string, or the number literal.
+    pub span: Span,
+}
+
+pub type Spanned<'a> = (usize, Token<'a>, usize);
+
+/// A lexer producing tokens together with their start and end offsets.
+/// Comments nest and are skipped by default.
+///
+/// A `-` directly followed by a digit is part of a negative number literal
+/// unless the previous token can end an expression. Thus, `f -1` means
+/// `f - 1`, like in OCaml, but `f (-1)` and `x * -1` contain the literal `-1`.
+pub struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    comments: bool,
+    /// The last token other than a comment.
+    last: Option<Token<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            pos: 0,
+            comments: false,
+            last: None,
+        }
+    }
+
+    /// Produce comments as tokens rather than skipping them.
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn next_token(&mut self) -> Option<Result<Spanned<'a>, LexicalError>> {
+        loop {
+            let rest = self.rest();
+            self.pos += rest.len() - rest.trim_start().len();
+            let start = self.pos;
+            let c = self.rest().chars().next()?;
+            if self.rest().starts_with("(*") {
+                if let Err(err) = self.comment() {
+                    return Some(Err(err));
+                }
+                if self.comments {
+                    let comment = Token::Comment(&self.source[start..self.pos]);
+                    return Some(Ok((start, comment, self.pos)));
+                }
+                continue;
+            }
+            return Some(self.token(c).map(|token| (start, token, self.pos)));
+        }
+    }
+
+    /// Skip a comment, which can contain nested comments.
+    fn comment(&mut self) -> Result<(), LexicalError> {
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("(*") {
+                depth += 1;
+                self.pos += 2;
+            } else if rest.starts_with("*)") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return Ok(());
+                }
+            } else if let Some(c) = rest.chars().next() {
+                self.pos += c.len_utf8();
+            } else {
+                return Err(LexicalError {
+                    kind: LexicalErrorKind::UnterminatedComment,
+                    span: Span::new(start, start + 2),
+                });
+            }
+        }
+    }
+
+    fn token(&mut self, c: char) -> Result<Token<'a>, LexicalError> {
+        use Token::*;
+        let start = self.pos;
+        let rest = self.rest();
+        if c.is_ascii_lowercase() {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            self.pos += len;
+            let token = match &rest[..len] {
+                "fun" => Fun,
+                "let" => Let,
+                "rec" => Rec,
+                "in" => In,
+                "fix" => Fix,
+                "if" => If,
+                "then" => Then,
+                "else" => Else,
+                "true" => True,
+                "false" => False,
+                "not" => Not,
+                "mod" => Mod,
+                "land" => Land,
+                "lor" => Lor,
+                "lxor" => Lxor,
+                "lsl" => Lsl,
+                "lsr" => Lsr,
+                "asr" => Asr,
+                "abs" => Abs,
+                "min" => Min,
+                "max" => Max,
+                "ref" => Ref,
+                "raise" => Raise,
+                "try" => Try,
+                "with" => With,
+                "handle" => Handle,
+                "perform" => Perform,
+                "return" => Return,
+                "callcc" => Callcc,
+                "throw" => Throw,
+                "reset" => Reset,
+                "shift" => Shift,
+                "and" => And,
+                "without" => Without,
+                "hd" => Hd,
+                "tl" => Tl,
+                ident => Ident(ident),
+            };
+            return Ok(token);
+        }
+        let negative = c == '-'
+            && rest[1..].starts_with(|c: char| c.is_ascii_digit())
+            && !self.last.is_some_and(|token| token.can_end_expr());
+        if c.is_ascii_digit() || negative {
+            let len = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(rest.len(), |len| len + 1);
+            self.pos += len;
+            return Ok(Number(&rest[..len]));
+        }
+        if c == '"' {
+            return self.string();
+        }
+        let two = [
+            ("->", Arrow),
+            ("==", Equals),
+            ("!=", NotEq),
+            ("<=", LessEq),
+            (">=", GreaterEq),
+            ("**", StarStar),
+            (":=", ColonEq),
+            ("::", ColonColon),
+            ("&&", AndAnd),
+            ("||", OrOr),
+        ];
+        if let Some((text, token)) = two.iter().find(|(text, _)| rest.starts_with(text)) {
+            self.pos += text.len();
+            return Ok(*token);
+        }
+        let token = match c {
+            '(' => LParen,
+            ')' => RParen,
+            '{' => LBrace,
+            '}' => RBrace,
+            '[' => LBracket,
+            ']' => RBracket,
+            '.' => Dot,
+            ';' => Semi,
+            ',' => Comma,
+            '=' => Assign,
+            '+' => Plus,
+            '-' => Minus,
+            '*' => Star,
+            '/' => Slash,
+            '<' => Less,
+            '>' => Greater,
+            '!' => Bang,
+            '|' => Bar,
+            _ => {
+                self.pos += c.len_utf8();
+                return Err(LexicalError {
+                    kind: LexicalErrorKind::InvalidCharacter(c),
+                    span: Span::new(start, self.pos),
+                });
+            }
+        };
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /// Lex a string literal. Strings cannot span multiple lines and `\`
+    /// escapes the next character.
+    fn string(&mut self) -> Result<Token<'a>, LexicalError> {
+        let start = self.pos;
+        let mut end = self.source.len();
+        let mut chars = self.rest().char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(Token::String(&self.source[start..self.pos]));
+                }
+                '\\' => {
+                    chars.next();
+                }
+                '\n' | '\r' => {
+                    end = start + i;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        // NOTE(MH): Lexing continues on the next line.
+        self.pos = end;
+        Err(LexicalError {
+            kind: LexicalErrorKind::UnterminatedString,
+            span: Span::new(start, start + 1),
+        })
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Spanned<'a>, LexicalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_token();
+        if let Some(Ok((_, token, _))) = result {
+            if !matches!(token, Token::Comment(_)) {
+                self.last = Some(token);
+            }
+        }
+        result
+    }
+}
+
+impl<'a> Token<'a> {
+    /// Whether an expression can end with this token.
+    fn can_end_expr(&self) -> bool {
+        use Token::*;
+        matches!(
+            self,
+            Ident(_)
+                | Number(_)
+                | String(_)
+                | True
+                | False
+                | Fix
+                | Not
+                | Abs
+                | Min
+                | Max
+                | Ref
+                | Raise
+                | Callcc
+                | Throw
+                | Reset
+                | Shift
+                | Hd
+                | Tl
+                | RParen
+                | RBrace
+                | RBracket
+        )
+    }
+
+    pub fn class(&self) -> TokenClass {
+        use Token::*;
+        match self {
+            Fun | Let | Rec | In | Fix | If | Then | Else | True | False | Not | Mod | Land
+            | Lor | Lxor | Lsl | Lsr | Asr | Abs | Min | Max | Ref | Raise | Try | With
+            | Handle | Perform | Return | Callcc | Throw | Reset | Shift | And | Without | Hd
+            | Tl => TokenClass::Keyword,
+            Ident(_) => TokenClass::Identifier,
+            Number(_) => TokenClass::Number,
+            String(_) => TokenClass::String,
+            Comment(_) => TokenClass::Comment,
+            LParen | RParen | LBrace | RBrace | LBracket | RBracket => TokenClass::Bracket,
+            Dot | Semi | Comma | Assign | Arrow | Plus | Minus | Star | Slash | Equals | NotEq
+            | Less | LessEq | Greater | GreaterEq | StarStar | Bang | ColonEq | AndAnd | OrOr
+            | Bar | ColonColon => TokenClass::Operator,
+        }
+    }
+}
+
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Token::*;
+        let text = match self {
+            Fun => "fun",
+            Let => "let",
+            Rec => "rec",
+            In => "in",
+            Fix => "fix",
+            If => "if",
+            Then => "then",
+            Else => "else",
+            True => "true",
+            False => "false",
+            Not => "not",
+            Mod => "mod",
+            Land => "land",
+            Lor => "lor",
+            Lxor => "lxor",
+            Lsl => "lsl",
+            Lsr => "lsr",
+            Asr => "asr",
+            Abs => "abs",
+            Min => "min",
+            Max => "max",
+            Ref => "ref",
+            Raise => "raise",
+            Try => "try",
+            With => "with",
+            Handle => "handle",
+            Perform => "perform",
+            Return => "return",
+            Callcc => "callcc",
+            Throw => "throw",
+            Reset => "reset",
+            Shift => "shift",
+            And => "and",
+            Without => "without",
+            Hd => "hd",
+            Tl => "tl",
+            Ident(text) | Number(text) | String(text) | Comment(text) => text,
+            LParen => "(",
+            RParen => ")",
+            LBrace => "{",
+            RBrace => "}",
+            LBracket => "[",
+            RBracket => "]",
+            Dot => ".",
+            Semi => ";",
+            Comma => ",",
+            Assign => "=",
+            Arrow => "->",
+            Plus => "+",
+            Minus => "-",
+            Star => "*",
+            Slash => "/",
+            Equals => "==",
+            NotEq => "!=",
+            Less => "<",
+            LessEq => "<=",
+            Greater => ">",
+            GreaterEq => ">=",
+            StarStar => "**",
+            Bang => "!",
+            ColonEq => ":=",
+            AndAnd => "&&",
+            OrOr => "||",
+            Bar => "|",
+            ColonColon => "::",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl fmt::Display for LexicalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LexicalErrorKind::InvalidCharacter(c) => write!(f, "unexpected character `{}`", c),
+            LexicalErrorKind::UnterminatedComment => write!(f, "unterminated comment"),
+            LexicalErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexicalErrorKind::NumberOutOfRange(n) => {
+                write!(f, "number literal {} is out of range", n)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Token::*;
+
+    fn lex(source: &str) -> Vec<Result<(&str, Token<'_>), LexicalError>> {
+        Lexer::new(source)
+            .map(|result| result.map(|(start, token, end)| (&source[start..end], token)))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        let tokens: Vec<_> = lex("let f = fun x_1 -> x_1.y<=10 in f{}")
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                ("let", Let),
+                ("f", Ident("f")),
+                ("=", Assign),
+                ("fun", Fun),
+                ("x_1", Ident("x_1")),
+                ("->", Arrow),
+                ("x_1", Ident("x_1")),
+                (".", Dot),
+                ("y", Ident("y")),
+                ("<=", LessEq),
+                ("10", Number("10")),
+                ("in", In),
+                ("f", Ident("f")),
+                ("{", LBrace),
+                ("}", RBrace),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keywords() {
+        for keyword in KEYWORDS {
+            let tokens = lex(keyword);
+            assert_eq!(tokens.len(), 1);
+            let (_, token) = tokens[0].clone().unwrap();
+            assert_eq!(token.class(), TokenClass::Keyword);
+            assert_eq!(token.to_string(), *keyword);
+        }
+        assert_eq!(lex("lets"), vec![Ok(("lets", Ident("lets")))]);
+    }
+
+    #[test]
+    fn test_negative_numbers() {
+        let tokens = |source| -> Vec<_> {
+            lex(source)
+                .into_iter()
+                .map(|result| result.unwrap().1)
+                .collect()
+        };
+        assert_eq!(tokens("-1"), vec![Number("-1")]);
+        assert_eq!(tokens("f -1"), vec![Ident("f"), Minus, Number("1")]);
+        assert_eq!(tokens("x-1"), vec![Ident("x"), Minus, Number("1")]);
+        assert_eq!(
+            tokens("f (-12)"),
+            vec![Ident("f"), LParen, Number("-12"), RParen]
+        );
+        assert_eq!(tokens("2*-3"), vec![Number("2"), Star, Number("-3")]);
+        assert_eq!(tokens("1 - -2"), vec![Number("1"), Minus, Number("-2")]);
+        assert_eq!(tokens("- 1"), vec![Minus, Number("1")]);
+        assert_eq!(tokens("-x"), vec![Minus, Ident("x")]);
+        assert_eq!(tokens("x (* c *) -1"), vec![Ident("x"), Minus, Number("1")]);
+        assert_eq!(tokens("not -1"), vec![Not, Minus, Number("1")]);
+    }
+
+    #[test]
+    fn test_boolean_operators() {
+        assert_eq!(
+            lex("a&&b||c"),
+            vec![
+                Ok(("a", Ident("a"))),
+                Ok(("&&", AndAnd)),
+                Ok(("b", Ident("b"))),
+                Ok(("||", OrOr)),
+                Ok(("c", Ident("c"))),
+            ]
+        );
+        assert_eq!(
+            lex("a|b"),
+            vec![Ok(("a", Ident("a"))), Ok(("|", Bar)), Ok(("b", Ident("b"))),]
+        );
+        assert_eq!(
+            lex("&"),
+            vec![Err(LexicalError {
+                kind: LexicalErrorKind::InvalidCharacter('&'),
+                span: Span::new(0, 1),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(
+            lex("x::[]"),
+            vec![
+                Ok(("x", Ident("x"))),
+                Ok(("::", ColonColon)),
+                Ok(("[", LBracket)),
+                Ok(("]", RBracket)),
+            ]
+        );
+        assert_eq!(
+            lex(":"),
+            vec![Err(LexicalError {
+                kind: LexicalErrorKind::InvalidCharacter(':'),
+                span: Span::new(0, 1),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_nested_comments() {
+        assert_eq!(
+            lex("1 (* a (* b *) c *) 2 (* d *)"),
+            vec![Ok(("1", Number("1"))), Ok(("2", Number("2")))]
+        );
+        let comments: Vec<_> = Lexer::new("(* a (* b *) *) x (**)")
+            .with_comments()
+            .map(|result| result.unwrap().1)
+            .collect();
+        assert_eq!(
+            comments,
+            vec![Comment("(* a (* b *) *)"), Ident("x"), Comment("(**)")]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |kind, start, end| {
+            Err(LexicalError {
+                kind,
+                span: Span::new(start, end),
+            })
+        };
+        assert_eq!(
+            lex("1 (* a (* b *) c"),
+            vec![
+                Ok(("1", Number("1"))),
+                error(LexicalErrorKind::UnterminatedComment, 2, 4)
+            ]
+        );
+        assert_eq!(
+            lex("x \"abc\\\" d\ny"),
+            vec![
+                Ok(("x", Ident("x"))),
+                error(LexicalErrorKind::UnterminatedString, 2, 3),
+                Ok(("y", Ident("y"))),
+            ]
+        );
+        assert_eq!(
+            lex("\"a\\\"b\" # ä"),
+            vec![
+                Ok(("\"a\\\"b\"", String("\"a\\\"b\""))),
+                error(LexicalErrorKind::InvalidCharacter('#'), 7, 8),
+                error(LexicalErrorKind::InvalidCharacter('ä'), 9, 11),
+            ]
+        );
+    }
+}
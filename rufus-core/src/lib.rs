@@ -1,7 +1,11 @@
 use lalrpop_util::lalrpop_mod;
 
 pub mod cek;
+pub mod diagnostics;
 pub mod humanizer;
+pub mod lexer;
+pub mod parse;
+pub mod prelude;
 pub mod syntax;
 
 lalrpop_mod!(
@@ -13,21 +17,47 @@ lalrpop_mod!(
 mod tests {
     fn integration_test(expected: i64, expr: &str) {
         use crate::cek::*;
-        use crate::parser::ExprParser;
-        let parser = ExprParser::new();
-        let expr = parser.parse(expr).unwrap().index().unwrap();
+        let expr = crate::parse::expr(expr)
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap();
         assert_eq!(
             Machine::new(&expr).run().unwrap().as_i64().unwrap(),
             expected
         );
     }
 
+    /// Run an expression and render its value, or return the error.
+    fn run(expr: &str) -> Result<String, String> {
+        use crate::cek::*;
+        let expr = crate::parse::expr(expr)
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap();
+        let result = Machine::new(&expr).run();
+        result.map(|value| value.to_string())
+    }
+
+    /// Run an example with the prelude in scope.
     fn example_test(expected: i64, path: &str) {
-        use std::io::Read;
-        let mut file = std::fs::File::open(path).unwrap();
-        let mut expr = String::new();
-        file.read_to_string(&mut expr).unwrap();
-        integration_test(expected, &expr)
+        use crate::cek::*;
+        use crate::prelude::Prelude;
+        let source = std::fs::read_to_string(path).unwrap();
+        let prelude = Prelude::get();
+        let expr = crate::parse::expr(&source)
+            .into_result()
+            .unwrap()
+            .index_in(prelude.names())
+            .unwrap();
+        let (env, store) = prelude.scope();
+        let value = Machine::new(&expr)
+            .with_env(env)
+            .with_store(store)
+            .run()
+            .unwrap();
+        assert_eq!(value.as_i64().unwrap(), expected);
     }
 
     #[test]
@@ -83,6 +113,707 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tuples() {
+        integration_test(
+            7,
+            "
+            let swap = fun (x, y) -> (y, x) in
+            let (a, (b, c)) = (1, swap (2, 4)) in
+            a + b + c
+            ",
+        );
+        integration_test(5, "let t = ((1, 2), 3) in t.0.1 + (t.1, t).0");
+        integration_test(1, "if (1, (true, 2)) == (1, (true, 2)) then 1 else 0");
+        integration_test(0, "if (1, 2) == (1, 2, 3) then 1 else 0");
+    }
+
+    #[test]
+    fn tuple_errors() {
+        let cases = vec![
+            ("(1, 2).2", "tuple of size 2 has no component 2"),
+            (
+                "{ x = 1 }.0",
+                "expected tuple, found Record({\"x\": Num(1)})",
+            ),
+            ("(fun (x, y) -> x) 1", "expected tuple, found Num(1)"),
+            (
+                "let (x, y) = (1, 2, 3) in x",
+                "expected tuple of size 2, found Tuple([Num(1), Num(2), Num(3)])",
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn sequencing() {
+        integration_test(2, "1; 2");
+        integration_test(3, "let f = fun () -> 3 in (); f ()");
+        integration_test(1, "let r = { a = 1; b = (2; 3) } in r.b - r.a - 1");
+        integration_test(1, "let () = () in if () == () then 1 else 0");
+        integration_test(5, "let x = 1 in (fun y -> x; y + x) 4");
+    }
+
+    #[test]
+    fn references() {
+        integration_test(
+            55,
+            "
+            let sum = ref 0 in
+            let rec loop = fun i ->
+                if i <= 10 then (sum := !sum + i; loop (i + 1)) else () in
+            loop 1;
+            !sum
+            ",
+        );
+        integration_test(
+            3,
+            "
+            let counter = fun () ->
+                let n = ref 0 in
+                fun () -> n := !n + 1; !n in
+            let c = counter () in
+            let d = counter () in
+            c (); d (); c (); c ()
+            ",
+        );
+        integration_test(2, "let r = ref (ref 1) in !r := 2; !!r");
+        integration_test(1, "let r = ref 1 in if r == r && r != ref 1 then 1 else 0");
+    }
+
+    #[test]
+    fn reference_errors() {
+        let cases = vec![
+            ("!1", "expected ref, found Num(1)"),
+            ("true := 1", "expected ref, found Bool(true)"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        integration_test(
+            1,
+            "
+            let rec even = fun n -> if n == 0 then true else odd (n - 1)
+            and odd = fun n -> if n == 0 then false else even (n - 1) in
+            if even 10 && odd 7 && not (even 3) then 1 else 0
+            ",
+        );
+        integration_test(
+            19,
+            "
+            let k = 1 in
+            let rec a = fun n -> if n <= 0 then 0 else k + b (n - 1)
+            and b = fun n -> if n <= 0 then 0 else 2 * k + c (n - 1)
+            and c = fun n -> if n <= 0 then 0 else 3 * k + a (n - 1) in
+            a 10 + c 0
+            ",
+        );
+        integration_test(
+            2,
+            "let rec f = fun (x, y) z -> if x > 0 then g (x - 1) (y + z) else y and g = fun x y -> f (x, y) 1 in f (2, 0) 1",
+        );
+    }
+
+    #[test]
+    fn recursive_values() {
+        integration_test(
+            1,
+            "
+            let rec m = {
+                even = fun n -> n == 0 || m.odd (n - 1);
+                odd = fun n -> n != 0 && m.even (n - 1)
+            } in
+            if m.even 10 && m.odd 3 then 1 else 0
+            ",
+        );
+        integration_test(
+            720,
+            "
+            let rec ops = { base = 1; fac = fun n -> if n <= 1 then ops.base else n * ops.fac (n - 1) }
+            and twice = (fun f x -> f (f x), ())
+            in
+            twice.0 ops.fac 3
+            ",
+        );
+        integration_test(
+            10,
+            "
+            let rec tree = { size = fun () -> 1 + sub.0.size () + sub.1.size () }
+            and sub = ({ size = fun () -> 4 }, { size = fun () -> tree2.base })
+            and tree2 = { base = 5 } in
+            tree.size ()
+            ",
+        );
+    }
+
+    #[test]
+    fn recursive_values_are_shared() {
+        use crate::cek::*;
+        use std::rc::Rc;
+        let expr = crate::parse::expr("let rec m = { f = fun n -> m } in (m, m.f 0, (m.f 1).f 2)")
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap();
+        let value = Machine::new(&expr).run().unwrap();
+        match &*value {
+            Value::Tuple(values) => {
+                assert!(Rc::ptr_eq(&values[0], &values[1]));
+                assert!(Rc::ptr_eq(&values[0], &values[2]));
+            }
+            _ => panic!("expected tuple, found {:?}", value),
+        }
+    }
+
+    #[test]
+    fn recursive_values_are_freed() {
+        use crate::cek::*;
+        use std::rc::Rc;
+        let expr =
+            crate::parse::expr("let rec f = fun n -> if n == 0 then f else f (n - 1) in f 3")
+                .into_result()
+                .unwrap()
+                .index()
+                .unwrap();
+        let mut machine = Machine::new(&expr);
+        let value = Rc::downgrade(&machine.run().unwrap());
+        assert!(value.upgrade().is_some());
+        drop(machine);
+        assert!(value.upgrade().is_none());
+    }
+
+    #[test]
+    fn unindexed_invalid_let_rec() {
+        use crate::cek::*;
+        use crate::syntax::*;
+        let x = Binder {
+            name: "x".to_string(),
+            span: Span::new(0, 1),
+        };
+        let neg = Box::new(Expr::PrimOp(OpCode::Neg));
+        let bound = Expr::App(neg, vec![Expr::Num(1)], Span::new(0, 1));
+        let expr = Expr::LetRec(vec![(x, bound)], Box::new(Expr::Unit));
+        let result = Machine::new(&expr).run();
+        assert_eq!(result.unwrap_err(), "cannot define x with let rec");
+    }
+
+    #[test]
+    fn record_updates() {
+        integration_test(
+            13,
+            "let r = { x = 1; y = 2 } in let s = { r with x = 10 } in s.x + s.y + r.x",
+        );
+        integration_test(
+            6,
+            "let r = { x = 1 } in let s = { r and y = 2; z = 3 } in s.x + s.y + s.z",
+        );
+        integration_test(
+            5,
+            "let r = { x = 1; y = 2 } in { { r without x } and x = 3 }.x + r.y",
+        );
+        integration_test(
+            3,
+            "let f = fun r -> { r with n = r.n + 1 } in (f (f { n = 1 })).n",
+        );
+    }
+
+    #[test]
+    fn record_punning() {
+        integration_test(
+            3,
+            "let pair = fun x y -> { x; y } in let r = pair 1 2 in r.x + r.y",
+        );
+        integration_test(5, "let x = 5 in { { x = 1 } with x }.x");
+        integration_test(
+            6,
+            "let { x; y = (a, b) } = { x = 1; y = (2, 3); z = 4 } in x + a + b",
+        );
+        integration_test(10, "(fun { x } { y = z } -> x * z) { x = 2 } { y = 5 }");
+        integration_test(1, "let {} = { x = 1 } in 1");
+    }
+
+    #[test]
+    fn record_pattern_errors() {
+        let cases = vec![
+            ("let { z } = { x = 1 } in z", "unknown field in record: z"),
+            ("let { x } = 1 in x", "expected record, found Num(1)"),
+            ("try (fun { x = (a, b) } -> a) { x = 1 } with e -> 2", "2"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_or_else(|err| err), expected);
+        }
+    }
+
+    #[test]
+    fn record_update_errors() {
+        let cases = vec![
+            ("{ { x = 1 } with y = 2 }", "unknown field in record: y"),
+            ("{ { x = 1 } and x = 2 }", "field already in record: x"),
+            ("{ { x = 1 } without x; y }", "unknown field in record: y"),
+            ("{ 1 with x = 2 }", "expected record, found Num(1)"),
+            ("{ { x = 1; y = true } without x }", "{ y = true }"),
+            ("{ { x = 1 } without x }", "{}"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_or_else(|err| err), expected);
+        }
+    }
+
+    #[test]
+    fn lists() {
+        integration_test(3, "hd (tl (1 :: 3 :: []))");
+        integration_test(
+            6,
+            "let rec sum = fun xs -> if xs == [] then 0 else hd xs + sum (tl xs) in sum [1; 2; 3]",
+        );
+        integration_test(
+            1,
+            "if 1 :: [2] == [1; 2] && [1] != [1; 2] && [] != [()] then 1 else 0",
+        );
+        integration_test(
+            1,
+            "let rec go = fun acc n -> if n == 0 then acc else go (n :: acc) (n - 1) in hd (go [] 100000)",
+        );
+    }
+
+    #[test]
+    fn list_values() {
+        let cases = vec![
+            ("[1; 2 + 1; (3, 4)]", "[1; 3; (3, 4)]"),
+            ("1 :: 2 :: [3]", "[1; 2; 3]"),
+            ("tl [[]]", "[]"),
+            ("hd []", "hd of empty list"),
+            ("tl []", "tl of empty list"),
+            ("1 :: 2", "expected list, found Num(2)"),
+            ("hd (1, 2)", "expected list, found Tuple([Num(1), Num(2)])"),
+            ("try tl [] with e -> [e]", "[<error: tl of empty list>]"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_or_else(|err| err), expected);
+        }
+    }
+
+    #[test]
+    fn exceptions() {
+        integration_test(3, "try 1 + raise 2 with x -> x + 1");
+        integration_test(1, "try 1 with x -> 2");
+        integration_test(
+            3,
+            "let x = 1 in try (let x = 2 in fun y -> raise (x, y)) 3 with (y, z) -> z",
+        );
+        integration_test(
+            6,
+            "
+            let f = fun x -> try raise x with y -> raise (y + 1) in
+            try f 2 with z -> try raise z with w -> w * 2
+            ",
+        );
+        integration_test(
+            2,
+            "
+            let x = 1 in
+            let r = try (let y = 5 in raise y) with e -> x + e in
+            x + r - 5
+            ",
+        );
+    }
+
+    #[test]
+    fn effects() {
+        integration_test(3, "handle 1 + perform get () with get () k -> k 2");
+        integration_test(4, "handle 1 with | return x -> x + 3 | get () k -> k 2");
+        integration_test(11, "handle perform get () with | get () k -> k 1 + k 10");
+        integration_test(
+            40,
+            "let x = 10 in handle (let y = 2 in y * perform get x) with get z k -> k (z + 5) + x",
+        );
+        // Handlers are deep: the handler also handles effects after resuming.
+        integration_test(
+            7,
+            "handle perform get () + perform get () with | return x -> x + 1 | get () k -> k 3",
+        );
+        // Effects the closest handler does not handle are forwarded.
+        integration_test(
+            12,
+            "
+            handle
+                handle perform a 1 + perform b 2 with a x k -> k (x * 10)
+            with
+            | b (x, y) k -> k 0
+            | b x k -> k x
+            ",
+        );
+        integration_test(2, "try handle raise 1 with e () k -> k 0 with x -> x + 1");
+        integration_test(
+            6,
+            "handle try raise (perform e 2) with x -> x + 1 with e n k -> k (n + 3)",
+        );
+    }
+
+    #[test]
+    fn effect_errors() {
+        let cases = vec![
+            ("perform e 1", "unhandled effect: e"),
+            (
+                "handle perform e 1 with f x k -> k x",
+                "unhandled effect: e",
+            ),
+            (
+                "handle perform e 1 with e () k -> k 2",
+                "unhandled effect: e",
+            ),
+            (
+                "try perform e 1 with x -> x",
+                "<error: unhandled effect: e>",
+            ),
+            (
+                "handle try perform e 2 with x -> x with e n k -> raise (n + 3)",
+                "uncaught exception: 5",
+            ),
+            (
+                "handle 1 with | return (x, y) -> x | e x k -> k x",
+                "expected tuple, found Num(1)",
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_or_else(|err| err), expected);
+        }
+    }
+
+    #[test]
+    fn continuations() {
+        integration_test(3, "1 + callcc (fun k -> 10 + throw k 2)");
+        integration_test(5, "callcc (fun k -> 5)");
+        integration_test(
+            4,
+            "
+            let n = ref 0 in
+            let k = ref 0 in
+            let x = callcc (fun c -> k := c; 0) in
+            n := !n + 1;
+            if x < 3 then throw !k (x + 1) else !n
+            ",
+        );
+        integration_test(
+            8,
+            "let x = 1 in (let y = 5 in callcc (fun k -> let z = 2 in throw k z) + y) + x",
+        );
+    }
+
+    #[test]
+    fn delimited_continuations() {
+        integration_test(121, "1 + reset (fun () -> 10 + shift (fun k -> k (k 100)))");
+        integration_test(5, "reset (fun () -> 1 + shift (fun k -> 5))");
+        integration_test(
+            107,
+            "
+            let x = 1 in
+            reset (fun () ->
+                let y = 2 in
+                x + y + shift (fun k -> let z = 100 in k z + k x))
+            ",
+        );
+        integration_test(
+            3,
+            "reset (fun () -> 1 + reset (fun () -> shift (fun k -> 2)))",
+        );
+    }
+
+    #[test]
+    fn continuation_errors() {
+        let cases = vec![
+            ("shift (fun k -> 1)", "shift without enclosing reset"),
+            ("throw 1 2", "expected continuation, found Num(1)"),
+            ("callcc (fun k -> k)", "<cont>"),
+            ("try shift (fun k -> 1) with e -> 2", "2"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_or_else(|err| err), expected);
+        }
+    }
+
+    #[test]
+    fn faults_are_catchable() {
+        let cases = vec![
+            ("try 1 / 0 with e -> e", "<error: division by zero>"),
+            ("try {}.x with e -> 1", "1"),
+            ("try 1 + true with e -> 2", "2"),
+            ("try (fun (x, y) -> x) 1 with e -> 3", "3"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr), Ok(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn uncaught_exceptions() {
+        use crate::cek::*;
+        let cases = vec![
+            ("raise (1, true)", "uncaught exception: (1, true)"),
+            ("try raise 1 with e -> 1 / 0", "division by zero"),
+            ("try 1 with e -> raise 2", "1"),
+            ("try raise 1 with (a, b) -> 1", "uncaught exception: 1"),
+            ("try (try raise (1, 2) with () -> 1) with (a, b) -> b", "2"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_or_else(|err| err), expected);
+        }
+        let expr = crate::parse::expr("let rec loop = fun x -> loop x in try loop 0 with e -> 1")
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap();
+        let limits = Limits {
+            max_steps: Some(1000),
+            max_kont: None,
+        };
+        let result = Machine::new(&expr).with_limits(limits).run();
+        assert_eq!(result.unwrap_err(), "step limit of 1000 exceeded");
+    }
+
+    #[test]
+    fn negation() {
+        integration_test(-6, "let f = fun x -> 2 * x in -f 3");
+        integration_test(7, "let f = 8 in f -1");
+        integration_test(0, "let f = fun x -> x + 1 in f (-1)");
+        integration_test(i64::MIN, "-9223372036854775807 - 1");
+        integration_test(-4, "let x = 4 in - x");
+    }
+
+    #[test]
+    fn negation_overflow() {
+        assert_eq!(
+            run("let x = -9223372036854775808 in -x").unwrap_err(),
+            "integer overflow"
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        integration_test(1, "7 mod 3");
+        integration_test(-1, "-7 mod 3");
+        integration_test(1, "7 mod -3");
+        integration_test(-2, "-7 / 3");
+        integration_test(512, "2 ** 3 ** 2");
+        integration_test(4, "- 2 ** 2");
+        integration_test(-8, "-2 ** 3");
+        integration_test(1, "-1 ** 9223372036854775806");
+        integration_test(2, "6 land 3");
+        integration_test(7, "6 lor 3");
+        integration_test(5, "6 lxor 3");
+        integration_test(24, "3 lsl 3");
+        integration_test(-2, "-8 asr 2");
+        integration_test(i64::MAX, "-1 lsr 1");
+        integration_test(7, "abs (-7) + min 1 2 - max 1 2 + abs 1");
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        let cases = vec![
+            ("1 / 0", "division by zero"),
+            ("1 mod 0", "division by zero"),
+            ("-9223372036854775808 / -1", "integer overflow"),
+            ("9223372036854775807 + 1", "integer overflow"),
+            ("-9223372036854775808 - 1", "integer overflow"),
+            ("4611686018427387904 * 2", "integer overflow"),
+            ("abs (-9223372036854775808)", "integer overflow"),
+            ("2 ** 63", "integer overflow"),
+            ("2 ** 4294967296", "integer overflow"),
+            ("2 ** -1", "negative exponent -1"),
+            ("1 lsl 64", "shift amount 64 out of range"),
+            ("1 asr -1", "shift amount -1 out of range"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn boolean_operators() {
+        integration_test(1, "if true && not false then 1 else 0");
+        integration_test(1, "if false || 1 < 2 && 2 < 3 then 1 else 0");
+        integration_test(0, "if not (true || false) then 1 else 0");
+        // NOTE(MH): The right operands are never evaluated.
+        integration_test(
+            1,
+            "if false && 1 / 0 == 0 || true || 1 / 0 == 0 then 1 else 0",
+        );
+    }
+
+    #[test]
+    fn boolean_operators_type_errors() {
+        let cases = vec![
+            ("1 && true", "expected bool, found Num(1)"),
+            ("true && 1", "expected bool, found Num(1)"),
+            ("false || {}", "expected bool, found Record({})"),
+            ("not 0", "expected bool, found Num(0)"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(run(expr).unwrap_err(), expected);
+        }
+        assert_eq!(run("false && 1"), Ok("false".to_string()));
+    }
+
+    #[test]
+    fn step_limit() {
+        use crate::cek::*;
+        let expr = crate::parse::expr("let rec loop = fun x -> loop x in loop 0")
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap();
+        let limits = Limits {
+            max_steps: Some(1000),
+            max_kont: None,
+        };
+        let result = Machine::new(&expr).with_limits(limits).run();
+        assert_eq!(result.unwrap_err(), "step limit of 1000 exceeded");
+    }
+
+    #[test]
+    fn kont_limit() {
+        use crate::cek::*;
+        let expr = crate::parse::expr(
+            "let rec sum = fun n -> if n == 0 then 0 else n + sum (n - 1) in sum 1000",
+        )
+        .into_result()
+        .unwrap()
+        .index()
+        .unwrap();
+        let limits = Limits {
+            max_steps: None,
+            max_kont: Some(100),
+        };
+        let result = Machine::new(&expr).with_limits(limits).run();
+        assert_eq!(result.unwrap_err(), "stack limit of 100 exceeded");
+    }
+
+    #[test]
+    fn unbound_variable() {
+        use crate::syntax::Span;
+        let err = crate::parse::expr("let x = 1 in y")
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap_err();
+        assert_eq!(err.span, Span::new(13, 14));
+        assert_eq!(err.to_string(), "unbound variable: y");
+    }
+
+    #[test]
+    fn invalid_let_rec() {
+        use crate::syntax::Span;
+        let cases = vec![
+            ("let rec x = x + 1 in x", Span::new(8, 9)),
+            (
+                "let rec f = fun x -> x and r = { f = fun x -> r.f x; g = r.f } in r",
+                Span::new(27, 28),
+            ),
+            ("let rec t = (fun x -> x, t.0) in t", Span::new(8, 9)),
+        ];
+        for (expr, span) in cases {
+            let err = crate::parse::expr(expr)
+                .into_result()
+                .unwrap()
+                .index()
+                .unwrap_err();
+            assert_eq!(err.span, span);
+            assert!(err.message.starts_with("cannot define"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn duplicate_let_rec() {
+        use crate::syntax::{IndexErrorKind, Span};
+        let err = crate::parse::expr("let rec f = fun x -> 1 and f = fun x -> 2 in f 0")
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap_err();
+        assert_eq!(err.kind, IndexErrorKind::DuplicateLetRec);
+        assert_eq!(err.span, Span::new(27, 28));
+        assert_eq!(err.message, "f is defined more than once in let rec");
+        integration_test(2, "let rec f = fun x -> 1 in let rec f = fun x -> 2 in f 0");
+    }
+
+    #[test]
+    fn error_spans() {
+        use crate::cek::*;
+        use crate::syntax::Span;
+        let cases = vec![
+            ("1 + true", Some((2, 3))),
+            ("let f = fun x -> x / 0 in f 1", Some((19, 20))),
+            ("-true", Some((0, 1))),
+            ("!1", Some((0, 1))),
+            ("hd []", Some((0, 5))),
+            ("raise 1", Some((0, 7))),
+            ("(try 1 / 0 with e -> true) && 1", None),
+            ("if 1 then 2 else 3", None),
+        ];
+        for (expr, expected) in cases {
+            let expr = crate::parse::expr(expr)
+                .into_result()
+                .unwrap()
+                .index()
+                .unwrap();
+            let mut machine = Machine::new(&expr);
+            assert!(machine.run().is_err());
+            let expected = expected.map(|(start, end)| Span::new(start, end));
+            assert_eq!(machine.error_span(), expected);
+        }
+    }
+
+    #[test]
+    fn type_name() {
+        use crate::cek::*;
+        let cases = vec![
+            ("1", "int"),
+            ("1 < 2", "bool"),
+            ("fun x y -> x", "fun/2"),
+            ("(fun x y -> x) 1", "fun/1"),
+            ("{}", "{}"),
+            ("()", "unit"),
+            ("ref 1", "ref"),
+            ("{ { x = 1 } and y = true }", "{ x : int; y : bool }"),
+            ("callcc (fun k -> k)", "cont"),
+            ("[]", "list"),
+            ("[[1]; []]", "int list list"),
+            ("(1, (true, fix))", "(int * (bool * fun/1))"),
+            (
+                "{ y = true; x = { f = fix } }",
+                "{ x : { f : fun/1 }; y : bool }",
+            ),
+        ];
+        for (expr, expected) in cases {
+            let expr = crate::parse::expr(expr)
+                .into_result()
+                .unwrap()
+                .index()
+                .unwrap();
+            let value = Machine::new(&expr).run().unwrap();
+            assert_eq!(value.type_name(), expected);
+        }
+    }
+
+    #[test]
+    fn keywords_match_grammar() {
+        let grammar = include_str!("parser.lalrpop");
+        let keywords: Vec<&str> = grammar
+            .lines()
+            .filter(|line| line.contains("=> Token::"))
+            .filter_map(|line| line.trim().strip_prefix('"')?.split_once('"'))
+            .map(|(token, _)| token)
+            .filter(|token| token.chars().all(|c| c.is_ascii_lowercase()))
+            .collect();
+        assert_eq!(keywords, crate::lexer::KEYWORDS);
+    }
+
     #[test]
     fn fib() {
         example_test(55, "../examples/fib.ml")
@@ -92,4 +823,19 @@ mod tests {
     fn list() {
         example_test(55, "../examples/list.ml")
     }
+
+    #[test]
+    fn generators() {
+        example_test(55, "../examples/generators.ml")
+    }
+
+    #[test]
+    fn state() {
+        example_test(55, "../examples/state.ml")
+    }
+
+    #[test]
+    fn nondet() {
+        example_test(6, "../examples/nondet.ml")
+    }
 }
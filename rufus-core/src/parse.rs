@@ -0,0 +1,167 @@
+use lalrpop_util::{ErrorRecovery, ParseError};
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Lexer, LexicalError};
+use crate::parser::{ExprParser, InputParser};
+use crate::syntax::{Expr, Input};
+
+/// A syntax error with the offending token rendered as a string.
+pub type SyntaxError = ParseError<usize, String, LexicalError>;
+
+/// The result of parsing with error recovery. The syntax tree contains an
+/// `Expr::Error` node for every part of the source the parser recovered from.
+#[derive(Debug)]
+pub struct Parsed<T> {
+    /// The syntax tree or `None` if the parser could not recover.
+    pub tree: Option<T>,
+    pub errors: Vec<SyntaxError>,
+}
+
+/// Parse an expression, reporting all syntax errors.
+pub fn expr(source: &str) -> Parsed<Expr> {
+    let mut errors = Vec::new();
+    let result = ExprParser::new().parse(&mut errors, Lexer::new(source));
+    Parsed::new(result, errors)
+}
+
+/// Parse a REPL input, reporting all syntax errors.
+pub fn input(source: &str) -> Parsed<Input> {
+    let mut errors = Vec::new();
+    let result = InputParser::new().parse(&mut errors, Lexer::new(source));
+    Parsed::new(result, errors)
+}
+
+impl<T> Parsed<T> {
+    fn new<Tok: ToString>(
+        result: Result<T, ParseError<usize, Tok, LexicalError>>,
+        errors: Vec<ErrorRecovery<usize, Tok, LexicalError>>,
+    ) -> Self {
+        let mut errors: Vec<_> = errors
+            .into_iter()
+            .map(|recovery| recovery.error.map_token(|token| token.to_string()))
+            .collect();
+        let tree = match result {
+            Ok(tree) => Some(tree),
+            Err(err) => {
+                errors.push(err.map_token(|token| token.to_string()));
+                None
+            }
+        };
+        Self { tree, errors }
+    }
+
+    /// Whether the source ended before the syntax tree was complete and
+    /// there are no other errors, i.e., more input could fix the errors.
+    pub fn is_incomplete(&self) -> bool {
+        !self.errors.is_empty()
+            && self
+                .errors
+                .iter()
+                .all(|err| matches!(err, ParseError::UnrecognizedEOF { .. }))
+    }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.errors
+            .iter()
+            .cloned()
+            .map(Diagnostic::from_parse_error)
+            .collect()
+    }
+
+    /// Get the syntax tree if there are no syntax errors and all errors
+    /// otherwise.
+    pub fn into_result(self) -> Result<T, Vec<Diagnostic>> {
+        match self.tree {
+            Some(tree) if self.errors.is_empty() => Ok(tree),
+            _ => Err(self.diagnostics()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &str) -> Vec<(usize, String)> {
+        expr(source)
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.primary_span().unwrap().start, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_ok() {
+        let parsed = expr("let x = 1 in x");
+        assert!(parsed.errors.is_empty());
+        assert!(parsed.into_result().is_ok());
+    }
+
+    #[test]
+    fn test_multiple_errors() {
+        let source = "let x = 1 + in\nlet y = { a = 1 *; b = ) } in\nx";
+        assert_eq!(
+            messages(source),
+            vec![
+                (12, "unexpected token `in`".to_string()),
+                (32, "unexpected token `;`".to_string()),
+                (38, "unexpected token `)`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partial_tree() {
+        let parsed = expr("let x = (1 +) in x + y");
+        assert_eq!(parsed.errors.len(), 1);
+        let tree = parsed.tree.unwrap();
+        assert_eq!(tree.pretty(), "let x = 1 + <error> in\nx + y");
+        let err = tree.index().unwrap_err();
+        assert_eq!(err.message, "unbound variable: y");
+    }
+
+    #[test]
+    fn test_negative_numbers() {
+        let parse = |source| expr(source).into_result().unwrap().pretty();
+        assert_eq!(parse("-9223372036854775808"), "-9223372036854775808");
+        assert_eq!(parse("- -9223372036854775808"), "--9223372036854775808");
+        assert_eq!(parse("x-1"), "x - 1");
+        assert_eq!(parse("2*-3"), "2 * -3");
+        assert_eq!(parse("- 5"), "-5");
+        assert_eq!(
+            messages("1 + -9223372036854775809"),
+            vec![(
+                4,
+                "number literal -9223372036854775809 is out of range".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_sequencing() {
+        let parse = |source| expr(source).into_result().unwrap().pretty();
+        assert_eq!(parse("let x = a; b in c; d"), "let x = a; b in\nc; d");
+        assert_eq!(
+            parse("if a then b; c else d; e"),
+            "if a then\n    b; c\nelse\n    d; e"
+        );
+        assert_eq!(
+            parse("{ a = fun x -> x; b = ()}"),
+            "{ a = fun x -> x; b = () }"
+        );
+        assert_eq!(parse("{ a = x; y; b = 1 }"), "{ a = x; y; b = 1 }");
+        assert_eq!(
+            messages("{ a = x; y + 1; b = 1 }")[0],
+            (11, "unexpected token `+`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_incomplete() {
+        assert!(input("let x = 1 in").is_incomplete());
+        assert!(input("let f = fun x ->").is_incomplete());
+        assert!(!input("let x = 1 in )").is_incomplete());
+        assert!(!input("let x = ) in").is_incomplete());
+        assert!(!input("let x = 1").is_incomplete());
+    }
+}
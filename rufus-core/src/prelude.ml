@@ -0,0 +1,61 @@
+(* The prelude is in scope in all programs unless it is disabled. Only its
+   leading `let`s matter, the final `()` is ignored.
+*)
+
+(* Combinators *)
+let id = fun x -> x in
+let const = fun x y -> x in
+let flip = fun f x y -> f y x in
+let compose = fun f g x -> f (g x) in
+let fst = fun (x, y) -> x in
+let snd = fun (x, y) -> y in
+let curry = fun f x y -> f (x, y) in
+let uncurry = fun f (x, y) -> f x y in
+
+(* Numbers *)
+let succ = fun n -> n + 1 in
+let pred = fun n -> n - 1 in
+let isEven = fun n -> n mod 2 == 0 in
+let isOdd = fun n -> n mod 2 != 0 in
+let sign = fun n -> if n < 0 then -1 else if n > 0 then 1 else 0 in
+let rec gcd = fun a b -> if b == 0 then abs a else gcd b (a mod b) in
+let clamp = fun lo hi n -> max lo (min hi n) in
+
+(* Options are records whose field `isSome` tells whether they have a field
+   `value`.
+*)
+let none = { isSome = false } in
+let some = fun value -> { isSome = true; value } in
+let getOr = fun default o -> if o.isSome then o.value else default in
+let mapOption = fun f o -> if o.isSome then some (f o.value) else none in
+
+(* Lists *)
+let cons = fun x xs -> x :: xs in
+let isEmpty = fun xs -> xs == [] in
+let rec foldl = fun f z xs ->
+    if xs == [] then z else foldl f (f z (hd xs)) (tl xs)
+in
+let rec foldr = fun f z xs ->
+    if xs == [] then z else f (hd xs) (foldr f z (tl xs))
+in
+let reverse = fun xs -> foldl (flip cons) [] xs in
+let length = fun xs -> foldl (fun n x -> n + 1) 0 xs in
+let sum = fun xs -> foldl (fun s x -> s + x) 0 xs in
+let append = fun xs ys -> foldl (flip cons) ys (reverse xs) in
+(* `map` and `filter` use `foldl` such that they work on long lists. *)
+let map = fun f xs -> reverse (foldl (fun ys x -> f x :: ys) [] xs) in
+let filter = fun p xs ->
+    reverse (foldl (fun ys x -> if p x then x :: ys else ys) [] xs)
+in
+let rec all = fun p xs -> xs == [] || p (hd xs) && all p (tl xs) in
+let rec any = fun p xs -> xs != [] && (p (hd xs) || any p (tl xs)) in
+let rec find = fun p xs ->
+    if xs == [] then none else if p (hd xs) then some (hd xs) else find p (tl xs)
+in
+let rec nth = fun xs n -> if n == 0 then hd xs else nth (tl xs) (n - 1) in
+(* The list `[lo; lo + 1; ...; hi - 1]`. *)
+let range = fun lo hi ->
+    let rec go = fun acc n -> if n < lo then acc else go (n :: acc) (n - 1) in
+    go [] (hi - 1)
+in
+()
@@ -0,0 +1,144 @@
+use std::sync::OnceLock;
+
+use crate::cek::{self, Env, Machine, Store};
+use crate::parse;
+use crate::syntax::{Expr, Name, Pattern};
+
+const SOURCE: &str = include_str!("prelude.ml");
+
+/// The bindings of the standard prelude, which are in scope in all programs
+/// unless the prelude is disabled. Programs using the prelude must be indexed
+/// in the scope of `names` and evaluated in the environment and store of
+/// `scope`.
+pub struct Prelude {
+    bindings: Vec<(Pattern, Expr)>,
+    names: Vec<Name>,
+}
+
+impl Prelude {
+    /// The prelude, which is parsed and indexed on first use.
+    pub fn get() -> &'static Self {
+        static PRELUDE: OnceLock<Prelude> = OnceLock::new();
+        PRELUDE.get_or_init(Self::load)
+    }
+
+    fn load() -> Self {
+        let expr = parse::expr(SOURCE)
+            .into_result()
+            .expect("prelude has syntax errors");
+        let (decls, _) = expr.into_decls();
+        let mut bindings = Vec::with_capacity(decls.len());
+        let mut names = Vec::new();
+        for decl in decls {
+            let (pattern, expr) = decl.into_binding();
+            let expr = expr.index_in(&names).expect("prelude has index errors");
+            names.extend(pattern.binders().into_iter().map(|x| x.name.clone()));
+            bindings.push((pattern, expr));
+        }
+        Self { bindings, names }
+    }
+
+    /// The names bound by the prelude. The last one is the innermost binding.
+    pub fn names(&self) -> &[Name] {
+        &self.names
+    }
+
+    /// The environment and store containing the values of the prelude's
+    /// bindings. The lambdas bound by its `let rec`s keep their environments
+    /// in the store, which is why programs need both.
+    // NOTE(MH): Values cannot be shared between threads, so we evaluate the
+    // prelude once per thread and hand out cheap copies of the result.
+    pub fn scope(&'static self) -> (Env<'static>, Store<'static>) {
+        thread_local! {
+            static SCOPE: (Env<'static>, Store<'static>) = Prelude::get().eval();
+        }
+        SCOPE.with(|(env, store)| (env.clone(), store.clone()))
+    }
+
+    fn eval(&self) -> (Env<'_>, Store<'_>) {
+        let mut env = Env::new();
+        let mut store = Store::new();
+        for (pattern, expr) in &self.bindings {
+            let mut machine = Machine::new(expr).with_env(env.clone()).with_store(store);
+            let value = machine.run().expect("prelude failed to evaluate");
+            store = machine.take_store();
+            env.push_many(cek::bind(pattern, value).expect("prelude failed to bind"));
+        }
+        (env, store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> String {
+        let prelude = Prelude::get();
+        let expr = parse::expr(source)
+            .into_result()
+            .unwrap()
+            .index_in(prelude.names())
+            .unwrap();
+        let (env, store) = prelude.scope();
+        let result = Machine::new(&expr).with_env(env).with_store(store).run();
+        result.map_or_else(|err| err, |value| value.to_string())
+    }
+
+    #[test]
+    fn test_combinators() {
+        assert_eq!(eval("compose succ (flip const 1) 2"), "3");
+        assert_eq!(eval("uncurry (curry fst) (id 1, 2)"), "1");
+        assert_eq!(eval("snd (1, 2)"), "2");
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(eval("gcd (-12) 18"), "6");
+        assert_eq!(eval("(sign (-3), sign 0, sign 7)"), "(-1, 0, 1)");
+        assert_eq!(
+            eval("(clamp 0 10 (-5), clamp 0 10 5, clamp 0 10 15)"),
+            "(0, 5, 10)"
+        );
+        assert_eq!(
+            eval("(isEven 4, isOdd (-3), pred (succ 1))"),
+            "(true, true, 1)"
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(eval("getOr 0 (mapOption succ (some 1))"), "2");
+        assert_eq!(eval("getOr 0 (mapOption succ none)"), "0");
+        assert_eq!(eval("getOr 0 (find isEven [1; 3; 4; 6])"), "4");
+        assert_eq!(eval("(find isEven [1; 3]).isSome"), "false");
+    }
+
+    #[test]
+    fn test_lists() {
+        let xs = "let xs = range 1 6 in ";
+        assert_eq!(eval(&format!("{}xs", xs)), "[1; 2; 3; 4; 5]");
+        assert_eq!(eval(&format!("{}(length xs, sum xs)", xs)), "(5, 15)");
+        assert_eq!(
+            eval(&format!("{}map succ (filter isOdd xs)", xs)),
+            "[2; 4; 6]"
+        );
+        assert_eq!(
+            eval(&format!("{}append (reverse xs) [0]", xs)),
+            "[5; 4; 3; 2; 1; 0]"
+        );
+        assert_eq!(eval(&format!("{}foldr cons [] xs == xs", xs)), "true");
+        assert_eq!(
+            eval(&format!("{}(all isOdd xs, any isOdd xs)", xs)),
+            "(false, true)"
+        );
+        assert_eq!(eval(&format!("{}(nth xs 2, isEmpty xs)", xs)), "(3, false)");
+        assert_eq!(eval("(range 1 1, isEmpty [])"), "([], true)");
+        assert_eq!(eval("length (map succ (range 0 10000))"), "10000");
+    }
+
+    #[test]
+    fn test_shadowing() {
+        assert_eq!(eval("let map = 1 in map + sum [map]"), "2");
+        assert_eq!(eval("nth [] 0"), "hd of empty list");
+    }
+}
@@ -1,22 +1,135 @@
 mod debruijn;
 mod iter;
+mod pretty;
+mod resolve;
+
+use std::fmt;
 
 use debruijn::Indexer;
 
+pub use resolve::Reference;
+
 pub type Name = String;
 
+/// A range of byte offsets into the source code.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// An occurrence of a name that introduces a variable.
+#[derive(Clone, Debug)]
+pub struct Binder {
+    pub name: Name,
+    pub span: Span,
+}
+
+/// A pattern in a binding position. Its variables are bound from left to
+/// right.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Var(Binder),
+    Unit,
+    /// A tuple pattern with at least two components.
+    Tuple(Vec<Pattern>),
+    /// A record pattern matching records with at least the given fields.
+    Record(Vec<(Name, Pattern)>),
+}
+
+/// A clause of an effect handler.
+#[derive(Clone, Debug)]
+pub enum Clause {
+    /// `return p -> e` handles the value of the handled expression. Without
+    /// such a clause, the value is passed through.
+    Return(Pattern, Expr),
+    /// `op p k -> e` handles the effect `op` whose argument matches `p`. The
+    /// continuation of `perform` is bound to `k`.
+    Effect(Name, Pattern, Binder, Expr),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IndexErrorKind {
+    UnboundVariable,
+    /// A binding of a `let rec` whose value is not a recursive value.
+    InvalidLetRec,
+    /// A name bound more than once by the same `let rec`.
+    DuplicateLetRec,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexError {
+    pub kind: IndexErrorKind,
+    pub span: Span,
+    pub message: String,
+}
+
 #[derive(Clone, Debug)]
 pub enum Expr {
-    Var(Name, Option<usize>),
+    Var(Name, Option<usize>, Span),
     Num(i64),
     Bool(bool),
+    Unit,
     PrimOp(OpCode),
-    App(Box<Expr>, Vec<Expr>),
-    Lam(Vec<Name>, Box<Expr>),
-    Let(Name, Box<Expr>, Box<Expr>),
+    /// An application, located at the operator for unary and binary
+    /// operators and spanning the whole application otherwise.
+    App(Box<Expr>, Vec<Expr>, Span),
+    Lam(Vec<Pattern>, Box<Expr>),
+    Let(Pattern, Box<Expr>, Box<Expr>),
+    /// `let rec f1 = e1 and ... and fn = en in e`. The `fi` are bound in all
+    /// the `ei` as well as in `e`. The `ei` are recursive values, see
+    /// `Expr::is_rec_value`.
+    LetRec(Vec<(Binder, Expr)>, Box<Expr>),
     If(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// Short-circuiting `&&`.
+    And(Box<Expr>, Box<Expr>),
+    /// Short-circuiting `||`.
+    Or(Box<Expr>, Box<Expr>),
+    /// Sequencing `e1; e2`, which discards the value of `e1`.
+    Seq(Box<Expr>, Box<Expr>),
+    /// `try e1 with p -> e2` evaluates `e2` if `e1` raises an exception
+    /// matching `p`.
+    Try(Box<Expr>, Pattern, Box<Expr>),
+    /// `handle e with | c1 | ... | cn` evaluates `e` and handles the effects
+    /// it performs with the clauses `ci`. Handlers are deep, i.e., they also
+    /// handle the effects performed after resuming a continuation.
+    Handle(Box<Expr>, Vec<Clause>),
+    /// `perform op e` performs the effect `op` with the value of `e` as
+    /// argument.
+    Perform(Name, Box<Expr>),
     Record(Vec<Name>, Vec<Expr>),
+    /// `{ e with x1 = e1; ...; xn = en }` copies the record `e` and changes
+    /// the fields `xi`, which must exist.
+    Update(Box<Expr>, Vec<Name>, Vec<Expr>),
+    /// `{ e and x1 = e1; ...; xn = en }` copies the record `e` and adds the
+    /// fields `xi`, which must not exist.
+    Extend(Box<Expr>, Vec<Name>, Vec<Expr>),
+    /// `{ e without x1; ...; xn }` copies the record `e` and removes the
+    /// fields `xi`, which must exist.
+    Restrict(Box<Expr>, Vec<Name>),
     Proj(Box<Expr>, Name),
+    /// A tuple with at least two components.
+    Tuple(Vec<Expr>),
+    /// Projection `e.i` of the zero-based component `i` of a tuple.
+    TupleProj(Box<Expr>, usize),
+    /// A list literal `[e1; ...; en]`.
+    List(Vec<Expr>),
+    /// A placeholder for a part of the source the parser recovered from.
+    Error(Span),
+}
+
+/// A top-level declaration, i.e., a `let` without an `in`.
+#[derive(Clone, Debug)]
+pub enum Decl {
+    Let(Pattern, Expr),
+    LetRec(Vec<(Binder, Expr)>),
+}
+
+/// A single input to the REPL.
+#[derive(Clone, Debug)]
+pub enum Input {
+    Decl(Decl),
+    Expr(Expr),
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -25,39 +138,162 @@ pub enum OpCode {
     Sub,
     Mul,
     Div,
+    Mod,
+    Pow,
+    Land,
+    Lor,
+    Lxor,
+    Lsl,
+    Lsr,
+    Asr,
+    Min,
+    Max,
     Equals,
     NotEq,
     Less,
     LessEq,
     Greater,
     GreaterEq,
+    Neg,
+    Abs,
+    Not,
     Fix,
+    Ref,
+    Deref,
+    Assign,
+    Raise,
+    Callcc,
+    Throw,
+    Reset,
+    Shift,
+    /// `x :: xs` prepends `x` to the list `xs`.
+    Cons,
+    Hd,
+    Tl,
 }
 
 impl Expr {
-    pub fn index(mut self) -> Result<Self, String> {
-        self.index_aux(&mut Indexer::new())?;
+    /// Build `-self`, folding the negation into number literals. The span is
+    /// the one of the minus sign.
+    pub fn negate(self, span: Span) -> Self {
+        match self {
+            Expr::Num(n) if n.checked_neg().is_some() => Expr::Num(-n),
+            e => Expr::App(Box::new(Expr::PrimOp(OpCode::Neg)), vec![e], span),
+        }
+    }
+
+    /// Split `let x1 = e1 in ... let xn = en in e` into the declarations
+    /// `let x1 = e1`, ..., `let xn = en` and the body `e`. The `let`s can
+    /// also be `let rec`s.
+    pub fn into_decls(self) -> (Vec<Decl>, Expr) {
+        let mut decls = Vec::new();
+        let mut expr = self;
+        loop {
+            match expr {
+                Expr::Let(x, e1, e2) => {
+                    decls.push(Decl::Let(x, *e1));
+                    expr = *e2;
+                }
+                Expr::LetRec(bindings, e) => {
+                    decls.push(Decl::LetRec(bindings));
+                    expr = *e;
+                }
+                _ => return (decls, expr),
+            }
+        }
+    }
+
+    /// Whether the expression can be bound by `let rec`. These are lambdas,
+    /// literals and records, tuples and lists thereof. Their values can be
+    /// built without using the values of the recursive bindings.
+    pub fn is_rec_value(&self) -> bool {
+        match self {
+            Expr::Lam(..) | Expr::Num(_) | Expr::Bool(_) | Expr::Unit => true,
+            Expr::Record(_, es) | Expr::Tuple(es) | Expr::List(es) => {
+                es.iter().all(Expr::is_rec_value)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn index(self) -> Result<Self, IndexError> {
+        self.index_in(&[])
+    }
+
+    /// Index the expression in a scope where the given names are bound. The
+    /// last name in `scope` is the innermost binding.
+    pub fn index_in(mut self, scope: &[Name]) -> Result<Self, IndexError> {
+        let mut indexer = Indexer::new();
+        let scope = scope.iter().map(String::as_str);
+        indexer.intro_many(scope, |indexer| self.index_aux(indexer))?;
         Ok(self)
     }
 
-    fn index_aux(&mut self, indexer: &mut Indexer) -> Result<(), String> {
+    fn index_aux(&mut self, indexer: &mut Indexer) -> Result<(), IndexError> {
         use Expr::*;
         match self {
-            Var(x, i @ None) => {
+            Var(x, i @ None, span) => {
                 if let Some(j) = indexer.get(x) {
                     *i = Some(j);
                 } else {
-                    return Err(format!("unbound variable: {}", x));
+                    return Err(IndexError {
+                        kind: IndexErrorKind::UnboundVariable,
+                        span: *span,
+                        message: format!("unbound variable: {}", x),
+                    });
                 }
             }
-            Var(_, Some(_)) => panic!("indexer running on indexed expression"),
-            Lam(xs, e) => {
-                // TODO(MH): Make this more efficient by using iterators.
+            Var(_, Some(_), _) => panic!("indexer running on indexed expression"),
+            Lam(ps, e) => {
+                let xs = ps
+                    .iter()
+                    .flat_map(Pattern::binders)
+                    .map(|x| x.name.as_str());
                 indexer.intro_many(xs, |indexer| e.index_aux(indexer))?;
             }
-            Let(x, e1, e2) => {
+            Let(p, e1, e2) | Try(e1, p, e2) => {
                 e1.index_aux(indexer)?;
-                indexer.intro(x, |indexer| e2.index_aux(indexer))?;
+                let xs = p.binders().into_iter().map(|x| x.name.as_str());
+                indexer.intro_many(xs, |indexer| e2.index_aux(indexer))?;
+            }
+            LetRec(bindings, e) => {
+                if let Some((f, _)) = bindings.iter().find(|(_, e)| !e.is_rec_value()) {
+                    return Err(IndexError {
+                        kind: IndexErrorKind::InvalidLetRec,
+                        span: f.span,
+                        message: format!(
+                            "cannot define {} with let rec: only functions and records, \
+                             tuples and lists of them can be defined recursively",
+                            f.name
+                        ),
+                    });
+                }
+                let xs: Vec<Name> = bindings.iter().map(|(f, _)| f.name.clone()).collect();
+                for (i, (f, _)) in bindings.iter().enumerate().skip(1) {
+                    if xs[..i].contains(&f.name) {
+                        return Err(IndexError {
+                            kind: IndexErrorKind::DuplicateLetRec,
+                            span: f.span,
+                            message: format!("{} is defined more than once in let rec", f.name),
+                        });
+                    }
+                }
+                let xs = xs.iter().map(String::as_str);
+                indexer.intro_many(xs, |indexer| {
+                    for (_, e) in bindings.iter_mut() {
+                        e.index_aux(indexer)?;
+                    }
+                    e.index_aux(indexer)
+                })?;
+            }
+            Handle(e, clauses) => {
+                e.index_aux(indexer)?;
+                for clause in clauses {
+                    let xs: Vec<Name> = clause.binders().iter().map(|x| x.name.clone()).collect();
+                    let xs = xs.iter().map(String::as_str);
+                    let body = clause.body_mut();
+                    indexer.intro_many(xs, |indexer| body.index_aux(indexer))?;
+                }
             }
             _ => {
                 for e in self.children_mut() {
@@ -69,12 +305,99 @@ impl Expr {
     }
 }
 
+impl Pattern {
+    /// The binders in the pattern from left to right.
+    pub fn binders(&self) -> Vec<&Binder> {
+        match self {
+            Pattern::Var(x) => vec![x],
+            Pattern::Unit => vec![],
+            Pattern::Tuple(ps) => ps.iter().flat_map(Pattern::binders).collect(),
+            Pattern::Record(fps) => fps.iter().flat_map(|(_, p)| p.binders()).collect(),
+        }
+    }
+
+    pub fn is_var(&self) -> bool {
+        matches!(self, Pattern::Var(_))
+    }
+}
+
+impl Decl {
+    /// Turn the declaration into a pattern and an expression whose value is
+    /// matched against it. Evaluating `let p = e in e'` has the same effect
+    /// as declaring `let p = e` and then evaluating `e'`.
+    pub fn into_binding(self) -> (Pattern, Expr) {
+        match self {
+            Decl::Let(pattern, expr) => (pattern, expr),
+            Decl::LetRec(bindings) => {
+                let mut binders: Vec<Binder> = bindings.iter().map(|(f, _)| f.clone()).collect();
+                let mut vars: Vec<Expr> = binders
+                    .iter()
+                    .map(|f| Expr::Var(f.name.clone(), None, f.span))
+                    .collect();
+                let (pattern, expr) = if binders.len() == 1 {
+                    (Pattern::Var(binders.remove(0)), vars.remove(0))
+                } else {
+                    let patterns = binders.into_iter().map(Pattern::Var).collect();
+                    (Pattern::Tuple(patterns), Expr::Tuple(vars))
+                };
+                (pattern, Expr::LetRec(bindings, Box::new(expr)))
+            }
+        }
+    }
+}
+
+impl Clause {
+    /// The binders of the clause from left to right, ending in the
+    /// continuation.
+    pub fn binders(&self) -> Vec<&Binder> {
+        match self {
+            Clause::Return(p, _) => p.binders(),
+            Clause::Effect(_, p, k, _) => {
+                let mut binders = p.binders();
+                binders.push(k);
+                binders
+            }
+        }
+    }
+
+    pub fn body(&self) -> &Expr {
+        match self {
+            Clause::Return(_, e) | Clause::Effect(_, _, _, e) => e,
+        }
+    }
+
+    pub fn body_mut(&mut self) -> &mut Expr {
+        match self {
+            Clause::Return(_, e) | Clause::Effect(_, _, _, e) => e,
+        }
+    }
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Check if the offset lies within the span. The end of the span counts
+    /// as inside such that a cursor right after a name is still on the name.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
 impl OpCode {
     pub fn arity(self) -> usize {
         use OpCode::*;
         match self {
-            Add | Sub | Mul | Div | Equals | NotEq | Less | LessEq | Greater | GreaterEq => 2,
-            Fix => 1,
+            Add | Sub | Mul | Div | Mod | Pow | Land | Lor | Lxor | Lsl | Lsr | Asr | Min | Max
+            | Equals | NotEq | Less | LessEq | Greater | GreaterEq | Assign | Throw | Cons => 2,
+            Neg | Abs | Not | Fix | Ref | Deref | Raise | Callcc | Reset | Shift | Hd | Tl => 1,
         }
     }
 }
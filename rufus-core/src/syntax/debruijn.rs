@@ -13,14 +13,13 @@ impl Indexer {
         }
     }
 
-    pub fn intro<T>(&mut self, x: &str, f: impl FnOnce(&mut Self) -> T) -> T {
-        self.intro_many(&[x.to_owned()], f)
-    }
-
-    pub fn intro_many<T>(&mut self, xs: &[String], f: impl FnOnce(&mut Self) -> T) -> T {
-        #[allow(clippy::needless_collect)]
-        let old_indices: Vec<(&String, Option<usize>)> = xs
-            .iter()
+    pub fn intro_many<'x, T>(
+        &mut self,
+        xs: impl IntoIterator<Item = &'x str>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let old_indices: Vec<(&str, Option<usize>)> = xs
+            .into_iter()
             .map(|x| {
                 let old_index = self.indices.insert(x.to_owned(), self.next_index);
                 self.next_index += 1;
@@ -28,7 +27,7 @@ impl Indexer {
             })
             .collect();
         let res = f(self);
-        self.next_index -= xs.len();
+        self.next_index -= old_indices.len();
         for (x, old_index) in old_indices.into_iter().rev() {
             if let Some(old_index) = old_index {
                 self.indices.insert(x.to_string(), old_index);
@@ -55,15 +54,15 @@ mod tests {
         assert_eq!(idx.get("x"), None);
         assert_eq!(idx.get("y"), None);
 
-        idx.intro("x", |idx| {
+        idx.intro_many(["x"], |idx| {
             assert_eq!(idx.get("x"), Some(1));
             assert_eq!(idx.get("y"), None);
 
-            idx.intro("y", |idx| {
+            idx.intro_many(["y"], |idx| {
                 assert_eq!(idx.get("x"), Some(2));
                 assert_eq!(idx.get("y"), Some(1));
 
-                idx.intro("x", |idx| {
+                idx.intro_many(["x"], |idx| {
                     assert_eq!(idx.get("x"), Some(1));
                     assert_eq!(idx.get("y"), Some(2));
                 });
@@ -71,7 +70,7 @@ mod tests {
                 assert_eq!(idx.get("x"), Some(2));
                 assert_eq!(idx.get("y"), Some(1));
 
-                idx.intro("y", |idx| {
+                idx.intro_many(["y"], |idx| {
                     assert_eq!(idx.get("x"), Some(3));
                     assert_eq!(idx.get("y"), Some(1));
                 });
@@ -91,7 +90,7 @@ mod tests {
     #[test]
     fn test_many() {
         let mut idx = Indexer::new();
-        idx.intro_many(&["x".to_string(), "y".to_string()], |idx| {
+        idx.intro_many(["x", "y"], |idx| {
             assert_eq!(idx.get("x"), Some(2));
             assert_eq!(idx.get("y"), Some(1));
         });
@@ -103,7 +102,7 @@ mod tests {
     #[test]
     fn test_many_shadowing() {
         let mut idx = Indexer::new();
-        idx.intro_many(&["x".to_string(), "x".to_string()], |idx| {
+        idx.intro_many(["x", "x"], |idx| {
             assert_eq!(idx.get("x"), Some(1));
         });
         assert_eq!(idx.get("x"), None);
@@ -1,22 +1,92 @@
 use super::*;
 
 impl Expr {
+    pub fn children(&self) -> impl Iterator<Item = &Expr> {
+        use genawaiter::{rc::gen, yield_};
+        use Expr::*;
+        gen!({
+            match self {
+                Var(..) | Num(_) | Bool(_) | Unit | PrimOp(_) | Error(_) => {}
+                App(f, es, _) => {
+                    yield_!(f.as_ref());
+                    for e in es {
+                        yield_!(e);
+                    }
+                }
+                Lam(_, e) | Proj(e, _) | TupleProj(e, _) | Perform(_, e) | Restrict(e, _) => {
+                    yield_!(e.as_ref());
+                }
+                Update(e, _, es) | Extend(e, _, es) => {
+                    yield_!(e.as_ref());
+                    for e in es {
+                        yield_!(e);
+                    }
+                }
+                LetRec(bindings, e) => {
+                    for (_, e) in bindings {
+                        yield_!(e);
+                    }
+                    yield_!(e.as_ref());
+                }
+                Handle(e, clauses) => {
+                    yield_!(e.as_ref());
+                    for clause in clauses {
+                        yield_!(clause.body());
+                    }
+                }
+                Let(_, e1, e2) | And(e1, e2) | Or(e1, e2) | Seq(e1, e2) | Try(e1, _, e2) => {
+                    yield_!(e1.as_ref());
+                    yield_!(e2.as_ref());
+                }
+                If(e1, e2, e3) => {
+                    yield_!(e1.as_ref());
+                    yield_!(e2.as_ref());
+                    yield_!(e3.as_ref());
+                }
+                Record(_, es) | Tuple(es) | List(es) => {
+                    for e in es {
+                        yield_!(e);
+                    }
+                }
+            }
+        })
+        .into_iter()
+    }
+
     pub fn children_mut(&mut self) -> impl Iterator<Item = &mut Expr> {
         use genawaiter::{rc::gen, yield_};
         use Expr::*;
         gen!({
             match self {
-                Var(..) | Num(_) | Bool(_) | PrimOp(_) => {}
-                App(f, es) => {
+                Var(..) | Num(_) | Bool(_) | Unit | PrimOp(_) | Error(_) => {}
+                App(f, es, _) => {
                     yield_!(f.as_mut());
                     for e in es {
                         yield_!(e);
                     }
                 }
-                Lam(_, e) | Proj(e, _) => {
+                Lam(_, e) | Proj(e, _) | TupleProj(e, _) | Perform(_, e) | Restrict(e, _) => {
+                    yield_!(e.as_mut());
+                }
+                Update(e, _, es) | Extend(e, _, es) => {
                     yield_!(e.as_mut());
+                    for e in es {
+                        yield_!(e);
+                    }
+                }
+                LetRec(bindings, e) => {
+                    for (_, e) in bindings {
+                        yield_!(e);
+                    }
+                    yield_!(e.as_mut());
+                }
+                Handle(e, clauses) => {
+                    yield_!(e.as_mut());
+                    for clause in clauses {
+                        yield_!(clause.body_mut());
+                    }
                 }
-                Let(_, e1, e2) => {
+                Let(_, e1, e2) | And(e1, e2) | Or(e1, e2) | Seq(e1, e2) | Try(e1, _, e2) => {
                     yield_!(e1.as_mut());
                     yield_!(e2.as_mut());
                 }
@@ -25,7 +95,7 @@ impl Expr {
                     yield_!(e2.as_mut());
                     yield_!(e3.as_mut());
                 }
-                Record(_, es) => {
+                Record(_, es) | Tuple(es) | List(es) => {
                     for e in es {
                         yield_!(e);
                     }
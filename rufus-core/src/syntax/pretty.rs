@@ -0,0 +1,644 @@
+use super::*;
+
+const INDENT: usize = 4;
+
+/// Precedence levels following the nonterminals in `parser.lalrpop`.
+const SEQ: u8 = 0;
+const EXPR: u8 = 1;
+const ASSIGN: u8 = 2;
+const OR: u8 = 3;
+const AND: u8 = 4;
+const COMP: u8 = 5;
+const CONS: u8 = 6;
+const SUM: u8 = 7;
+const PRODUCT: u8 = 8;
+const POW: u8 = 9;
+const NEG: u8 = 10;
+const APP: u8 = 11;
+const ARG: u8 = 12;
+const ATOM: u8 = 13;
+
+impl Expr {
+    /// Render the expression as source code. Parsing the result yields the
+    /// same expression again for all expressions produced by the parser.
+    /// Comments are not preserved.
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.pretty_aux(SEQ, 0, &mut out);
+        out
+    }
+
+    /// Render the expression at the given precedence level. Expressions
+    /// ending in another expression, like `fun`, render that expression at
+    /// the same level since it cannot be a sequence inside a record field.
+    fn pretty_aux(&self, prec: u8, indent: usize, out: &mut String) {
+        use Expr::*;
+        if self.prec() < prec {
+            self.pretty_parens(indent, out);
+            return;
+        }
+        match self {
+            Var(x, _, _) => out.push_str(x),
+            Num(n) => out.push_str(&n.to_string()),
+            Bool(b) => out.push_str(&b.to_string()),
+            Unit => out.push_str("()"),
+            PrimOp(op) if op.prec() == ATOM => out.push_str(op.symbol()),
+            PrimOp(op) => out.push_str(&format!("({})", op.symbol())),
+            App(fun, args, _) if unary_op(fun, args).is_some() => {
+                let op = unary_op(fun, args).unwrap();
+                out.push_str(if let OpCode::Neg = op {
+                    "-"
+                } else {
+                    op.symbol()
+                });
+                args[0].pretty_aux(op.prec(), indent, out);
+            }
+            App(fun, args, _) => match binary_op(fun, args) {
+                Some((op, lhs, rhs)) => {
+                    let (lhs_prec, rhs_prec) = match op.prec() {
+                        ASSIGN => (OR, OR),
+                        COMP => (CONS, CONS),
+                        CONS => (SUM, CONS),
+                        POW => (NEG, POW),
+                        prec => (prec, prec + 1),
+                    };
+                    lhs.pretty_aux(lhs_prec, indent, out);
+                    out.push_str(&format!(" {} ", op.symbol()));
+                    rhs.pretty_aux(rhs_prec, indent, out);
+                }
+                None => {
+                    fun.pretty_aux(ARG, indent, out);
+                    for arg in args {
+                        out.push(' ');
+                        arg.pretty_aux(ARG, indent, out);
+                    }
+                }
+            },
+            Lam(xs, body) => {
+                out.push_str("fun ");
+                out.push_str(&patterns(xs));
+                out.push_str(" ->");
+                body.pretty_block(prec, indent, out);
+            }
+            Let(p, bound, body) => {
+                out.push_str(&format!("let {} =", p));
+                bound.pretty_bound(indent, out);
+                if bound.is_simple() {
+                    out.push_str(" in");
+                } else {
+                    newline(indent, out);
+                    out.push_str("in");
+                }
+                newline(indent, out);
+                body.pretty_aux(prec, indent, out);
+            }
+            LetRec(bindings, body) => {
+                for (i, (f, bound)) in bindings.iter().enumerate() {
+                    if i == 0 {
+                        out.push_str("let rec ");
+                    } else {
+                        newline(indent, out);
+                        out.push_str("and ");
+                    }
+                    out.push_str(&format!("{} =", f.name));
+                    bound.pretty_bound(indent, out);
+                }
+                if bindings.iter().all(|(_, bound)| bound.is_simple()) {
+                    out.push_str(" in");
+                } else {
+                    newline(indent, out);
+                    out.push_str("in");
+                }
+                newline(indent, out);
+                body.pretty_aux(prec, indent, out);
+            }
+            If(cond, then, elze) => {
+                out.push_str("if ");
+                cond.pretty_aux(SEQ, indent, out);
+                out.push_str(" then");
+                newline(indent + INDENT, out);
+                then.pretty_aux(SEQ, indent + INDENT, out);
+                newline(indent, out);
+                out.push_str("else");
+                newline(indent + INDENT, out);
+                elze.pretty_aux(prec, indent + INDENT, out);
+            }
+            Try(body, pattern, handler) => {
+                out.push_str("try");
+                body.pretty_block(SEQ, indent, out);
+                if body.is_simple() {
+                    out.push(' ');
+                } else {
+                    newline(indent, out);
+                }
+                out.push_str(&format!("with {} ->", pattern));
+                handler.pretty_block(prec, indent, out);
+            }
+            Handle(body, clauses) => {
+                out.push_str("handle");
+                body.pretty_block(SEQ, indent, out);
+                if body.is_simple() {
+                    out.push_str(" with");
+                } else {
+                    newline(indent, out);
+                    out.push_str("with");
+                }
+                for (i, clause) in clauses.iter().enumerate() {
+                    newline(indent, out);
+                    match clause {
+                        Clause::Return(p, _) => out.push_str(&format!("| return {} ->", p)),
+                        Clause::Effect(op, p, k, _) => {
+                            out.push_str(&format!("| {} {} {} ->", op, p, k.name))
+                        }
+                    }
+                    let body = clause.body();
+                    if i + 1 == clauses.len() {
+                        body.pretty_block(prec, indent, out);
+                    } else if body.ends_in_handle() {
+                        newline(indent + INDENT, out);
+                        body.pretty_parens(indent + INDENT, out);
+                    } else {
+                        body.pretty_block(SEQ, indent, out);
+                    }
+                }
+            }
+            Perform(op, arg) => {
+                out.push_str(&format!("perform {} ", op));
+                arg.pretty_aux(ARG, indent, out);
+            }
+            Seq(first, second) => {
+                first.pretty_aux(ASSIGN, indent, out);
+                out.push(';');
+                if self.is_simple() {
+                    out.push(' ');
+                } else {
+                    newline(indent, out);
+                }
+                second.pretty_aux(SEQ, indent, out);
+            }
+            And(lhs, rhs) => {
+                lhs.pretty_aux(COMP, indent, out);
+                out.push_str(" && ");
+                rhs.pretty_aux(AND, indent, out);
+            }
+            Or(lhs, rhs) => {
+                lhs.pretty_aux(AND, indent, out);
+                out.push_str(" || ");
+                rhs.pretty_aux(OR, indent, out);
+            }
+            Record(fields, exprs) => {
+                if fields.is_empty() {
+                    out.push_str("{}");
+                } else {
+                    out.push_str("{ ");
+                    pretty_fields(fields, exprs, indent, out);
+                    out.push_str(" }");
+                }
+            }
+            Update(record, fields, exprs) | Extend(record, fields, exprs) => {
+                out.push_str("{ ");
+                record.pretty_field(indent, out);
+                out.push_str(if let Update(..) = self {
+                    " with "
+                } else {
+                    " and "
+                });
+                pretty_fields(fields, exprs, indent, out);
+                out.push_str(" }");
+            }
+            Restrict(record, fields) => {
+                out.push_str("{ ");
+                record.pretty_field(indent, out);
+                out.push_str(&format!(" without {} }}", fields.join("; ")));
+            }
+            Proj(record, field) => {
+                record.pretty_aux(ATOM, indent, out);
+                out.push('.');
+                out.push_str(field);
+            }
+            Tuple(exprs) => {
+                out.push('(');
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    expr.pretty_aux(SEQ, indent, out);
+                }
+                out.push(')');
+            }
+            TupleProj(tuple, index) => {
+                tuple.pretty_aux(ATOM, indent, out);
+                out.push_str(&format!(".{}", index));
+            }
+            List(exprs) => {
+                out.push('[');
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str("; ");
+                    }
+                    expr.pretty_field(indent, out);
+                }
+                out.push(']');
+            }
+            // NOTE(MH): This is not valid syntax, on purpose.
+            Error(_) => out.push_str("<error>"),
+        }
+    }
+
+    /// Render the expression bound by a `let`. Lambdas start on the same
+    /// line as the `let`.
+    fn pretty_bound(&self, indent: usize, out: &mut String) {
+        if let Expr::Lam(..) = self {
+            out.push(' ');
+            self.pretty_aux(SEQ, indent, out);
+        } else {
+            self.pretty_block(SEQ, indent, out);
+        }
+    }
+
+    /// Render the expression in a position where it cannot end in a sequence
+    /// or a `handle`, like a record field.
+    fn pretty_field(&self, indent: usize, out: &mut String) {
+        if self.ends_in_handle() {
+            self.pretty_parens(indent, out);
+        } else {
+            self.pretty_aux(EXPR, indent, out);
+        }
+    }
+
+    fn pretty_parens(&self, indent: usize, out: &mut String) {
+        out.push('(');
+        self.pretty_aux(SEQ, indent, out);
+        out.push(')');
+    }
+
+    /// Render the expression on the same line if it is simple and indented
+    /// on a new line otherwise.
+    fn pretty_block(&self, prec: u8, indent: usize, out: &mut String) {
+        if self.is_simple() {
+            out.push(' ');
+            self.pretty_aux(prec, indent, out);
+        } else {
+            newline(indent + INDENT, out);
+            self.pretty_aux(prec, indent + INDENT, out);
+        }
+    }
+
+    fn prec(&self) -> u8 {
+        use Expr::*;
+        match self {
+            Num(n) if *n < 0 => NEG,
+            Var(..) | Num(_) | Bool(_) | Unit | PrimOp(_) | Record(..) | Update(..)
+            | Extend(..) | Restrict(..) | Proj(..) | Tuple(_) | TupleProj(..) | List(_)
+            | Error(_) => ATOM,
+            App(fun, args, _) if unary_op(fun, args).is_some() => {
+                unary_op(fun, args).unwrap().prec()
+            }
+            App(fun, args, _) => match binary_op(fun, args) {
+                Some((op, _, _)) => op.prec(),
+                None => APP,
+            },
+            Perform(..) => APP,
+            And(..) => AND,
+            Or(..) => OR,
+            Lam(..) | Let(..) | LetRec(..) | If(..) | Try(..) | Handle(..) => EXPR,
+            Seq(..) => SEQ,
+        }
+    }
+
+    /// Whether the expression ends in a `handle`, whose last clause would
+    /// absorb anything following the expression, like further clauses or
+    /// record fields.
+    fn ends_in_handle(&self) -> bool {
+        use Expr::*;
+        match self {
+            Handle(..) => true,
+            Lam(_, e) | Let(_, _, e) | LetRec(_, e) | If(_, _, e) | Try(_, _, e) | Seq(_, e) => {
+                e.ends_in_handle()
+            }
+            _ => false,
+        }
+    }
+
+    /// Simple expressions are rendered on a single line.
+    fn is_simple(&self) -> bool {
+        match self {
+            Expr::Let(..) | Expr::LetRec(..) | Expr::If(..) | Expr::Handle(..) => false,
+            _ => self.children().all(Expr::is_simple),
+        }
+    }
+}
+
+impl OpCode {
+    fn symbol(self) -> &'static str {
+        use OpCode::*;
+        match self {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Mod => "mod",
+            Pow => "**",
+            Land => "land",
+            Lor => "lor",
+            Lxor => "lxor",
+            Lsl => "lsl",
+            Lsr => "lsr",
+            Asr => "asr",
+            Min => "min",
+            Max => "max",
+            Equals => "==",
+            NotEq => "!=",
+            Less => "<",
+            LessEq => "<=",
+            Greater => ">",
+            GreaterEq => ">=",
+            // NOTE(MH): This is OCaml's name for unary minus. It is only
+            // used when the operator is not applied.
+            Neg => "~-",
+            Abs => "abs",
+            Not => "not",
+            Fix => "fix",
+            Ref => "ref",
+            Deref => "!",
+            Assign => ":=",
+            Raise => "raise",
+            Callcc => "callcc",
+            Throw => "throw",
+            Reset => "reset",
+            Shift => "shift",
+            Cons => "::",
+            Hd => "hd",
+            Tl => "tl",
+        }
+    }
+
+    fn prec(self) -> u8 {
+        use OpCode::*;
+        match self {
+            Add | Sub => SUM,
+            Mul | Div | Mod | Land | Lor | Lxor => PRODUCT,
+            Pow | Lsl | Lsr | Asr => POW,
+            Equals | NotEq | Less | LessEq | Greater | GreaterEq => COMP,
+            Cons => CONS,
+            Neg => NEG,
+            Assign => ASSIGN,
+            Deref => ARG,
+            Abs | Min | Max | Not | Fix | Ref | Raise | Callcc | Throw | Reset | Shift | Hd
+            | Tl => ATOM,
+        }
+    }
+}
+
+fn newline(indent: usize, out: &mut String) {
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+}
+
+/// Render the fields of a record, punning `x = x` as `x`.
+fn pretty_fields(fields: &[Name], exprs: &[Expr], indent: usize, out: &mut String) {
+    for (i, (field, expr)) in fields.iter().zip(exprs).enumerate() {
+        if i > 0 {
+            out.push_str("; ");
+        }
+        out.push_str(field);
+        if !matches!(expr, Expr::Var(x, _, _) if x == field) {
+            out.push_str(" = ");
+            expr.pretty_field(indent, out);
+        }
+    }
+}
+
+fn patterns(ps: &[Pattern]) -> String {
+    let ps: Vec<String> = ps.iter().map(Pattern::to_string).collect();
+    ps.join(" ")
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Var(x) => write!(f, "{}", x.name),
+            Pattern::Unit => write!(f, "()"),
+            Pattern::Tuple(ps) => {
+                let ps: Vec<String> = ps.iter().map(Pattern::to_string).collect();
+                write!(f, "({})", ps.join(", "))
+            }
+            Pattern::Record(fps) if fps.is_empty() => write!(f, "{{}}"),
+            Pattern::Record(fps) => {
+                let fps: Vec<String> = fps
+                    .iter()
+                    .map(|(field, p)| match p {
+                        Pattern::Var(x) if &x.name == field => field.clone(),
+                        _ => format!("{} = {}", field, p),
+                    })
+                    .collect();
+                write!(f, "{{ {} }}", fps.join("; "))
+            }
+        }
+    }
+}
+
+/// Recognize the application of unary minus or dereferencing.
+fn unary_op(fun: &Expr, args: &[Expr]) -> Option<OpCode> {
+    match (fun, args) {
+        (Expr::PrimOp(op @ (OpCode::Neg | OpCode::Deref)), [_]) => Some(*op),
+        _ => None,
+    }
+}
+
+/// Recognize the application of a binary operator.
+fn binary_op<'a>(fun: &Expr, args: &'a [Expr]) -> Option<(OpCode, &'a Expr, &'a Expr)> {
+    match (fun, args) {
+        (Expr::PrimOp(op), [lhs, rhs]) if op.prec() < NEG => Some((*op, lhs, rhs)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    fn pretty(input: &str) -> String {
+        parse::expr(input).into_result().unwrap().pretty()
+    }
+
+    #[test]
+    fn test_fib() {
+        let input = std::fs::read_to_string("../examples/fib.ml").unwrap();
+        let expected = "\
+let rec fib = fun n ->
+    if n <= 1 then
+        1
+    else
+        fib (n - 2) + fib (n - 1)
+in
+fib 9";
+        assert_eq!(pretty(&input), expected);
+    }
+
+    #[test]
+    fn test_nested() {
+        let input = "let f = fun x -> let y = x * x in if y > 10 then y else {a = y; b = (fun z -> z) y}.a in f 3";
+        let expected = "\
+let f = fun x ->
+    let y = x * x in
+    if y > 10 then
+        y
+    else
+        { a = y; b = (fun z -> z) y }.a
+in
+f 3";
+        assert_eq!(pretty(input), expected);
+    }
+
+    #[test]
+    fn test_parens() {
+        let cases = vec![
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 + (2 * 3)", "1 + 2 * 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("(1 < 2) == true", "(1 < 2) == true"),
+            ("f (g x) (fun y -> y)", "f (g x) (fun y -> y)"),
+            ("(f x).y", "(f x).y"),
+            ("fix (fun f x -> f x)", "fix (fun f x -> f x)"),
+            ("f -1", "f - 1"),
+            ("f (-1)", "f (-1)"),
+            ("f (- 1)", "f (-1)"),
+            ("-x * y", "-x * y"),
+            ("-(x * y)", "-(x * y)"),
+            ("-f x", "-f x"),
+            ("(-f) x", "(-f) x"),
+            ("1 - -2", "1 - -2"),
+            ("x * - - y", "x * --y"),
+            ("(-1).x", "(-1).x"),
+            ("2 ** 3 ** 2", "2 ** 3 ** 2"),
+            ("(2 ** 3) ** 2", "(2 ** 3) ** 2"),
+            ("- 2 ** 2", "-2 ** 2"),
+            ("-(2 ** x)", "-(2 ** x)"),
+            ("2 ** -x * 3", "2 ** -x * 3"),
+            ("x mod 2 * 3", "x mod 2 * 3"),
+            ("x land (1 lsl n)", "x land 1 lsl n"),
+            ("min (abs x) y", "min (abs x) y"),
+            ("max x (-y)", "max x (-y)"),
+            ("((1, 2), (x + 1))", "((1, 2), x + 1)"),
+            ("(f x, y).0.1", "(f x, y).0.1"),
+            (
+                "let ((a, b), c) = t in fun (x) (y, z) -> a",
+                "let ((a, b), c) = t in\nfun x (y, z) -> a",
+            ),
+            ("f (); g ()", "f (); g ()"),
+            ("(a; b); c", "(a; b); c"),
+            (
+                "{ a = (x; y); b = fun x -> (x; y) }",
+                "{ a = (x; y); b = fun x -> (x; y) }",
+            ),
+            ("fun x -> x; y", "fun x -> x; y"),
+            ("(fun x -> x); y", "(fun x -> x); y"),
+            (
+                "(if a then b else c); d",
+                "(if a then\n    b\nelse\n    c);\nd",
+            ),
+            ("r := !r + 1; !r", "r := !r + 1; !r"),
+            ("f !r.x (!g) y", "f !r.x !g y"),
+            ("(!r).x", "(!r).x"),
+            ("!(f x)", "!(f x)"),
+            ("r := (fun x -> x)", "r := (fun x -> x)"),
+            ("(r := 1) == ()", "(r := 1) == ()"),
+            ("!!(ref (ref 1))", "!!(ref (ref 1))"),
+            ("try f x with e -> 0", "try f x with e -> 0"),
+            ("(try f x with e -> e); 1", "(try f x with e -> e); 1"),
+            (
+                "{ a = try x with e -> e; b = 1 }",
+                "{ a = try x with e -> e; b = 1 }",
+            ),
+            (
+                "try let x = 1 in x with (a, b) -> raise a",
+                "try\n    let x = 1 in\n    x\nwith (a, b) -> raise a",
+            ),
+            (
+                "handle f () with | return x -> x | get () k -> k 1",
+                "handle f () with\n| return x -> x\n| get () k -> k 1",
+            ),
+            (
+                "handle f () with | e x k -> (k x; k x) | e x k -> k x; 1",
+                "handle f () with\n| e x k -> k x; k x\n| e x k -> k x; 1",
+            ),
+            (
+                "handle f () with | e x k -> fun s -> (k x) s | return x -> fun s -> x",
+                "handle f () with\n| e x k -> fun s -> (k x) s\n| return x -> fun s -> x",
+            ),
+            (
+                "(handle x with e x k -> x); 1",
+                "(handle x with\n| e x k -> x);\n1",
+            ),
+            (
+                "handle x with | e x k -> (handle y with f y k -> y) | f y k -> y",
+                "handle x with\n| e x k ->\n    (handle y with\n    | f y k -> y)\n| f y k -> y",
+            ),
+            (
+                "handle x with | e x k -> fun y -> (handle y with f y k -> y) | f y k -> y",
+                "handle x with\n| e x k ->\n    (fun y ->\n        handle y with\n        | f y k -> y)\n| f y k -> y",
+            ),
+            (
+                "{ a = fun x -> (handle x with e y k -> y); b = 1 }",
+                "{ a = (fun x ->\n    handle x with\n    | e y k -> y); b = 1 }",
+            ),
+            (
+                "let rec f = fun x -> g x and g = fun y -> f y in f",
+                "let rec f = fun x -> g x\nand g = fun y -> f y in\nf",
+            ),
+            (
+                "let rec f = fun x -> if x then g x else 1 and g = fun y -> f y in f",
+                "let rec f = fun x ->\n    if x then\n        g x\n    else\n        1\nand g = fun y -> f y\nin\nf",
+            ),
+            (
+                "let rec m = { f = fun x -> m.g x; g = fun y -> y } in m.f",
+                "let rec m = { f = fun x -> m.g x; g = fun y -> y } in\nm.f",
+            ),
+            ("{ r with x = 1; y = f y }", "{ r with x = 1; y = f y }"),
+            ("{ f r and x = (a; b) }", "{ f r and x = (a; b) }"),
+            ("{ { r without x } without y; z }", "{ { r without x } without y; z }"),
+            ("{ r with x = 1 }.x", "{ r with x = 1 }.x"),
+            ("{ x = x; y = 1; z }", "{ x; y = 1; z }"),
+            ("{ r with x; y = x }", "{ r with x; y = x }"),
+            (
+                "let { x; y = (a, b) } = r in x",
+                "let { x; y = (a, b) } = r in\nx",
+            ),
+            ("fun {} { a = { b = c } } -> c", "fun {} { a = { b = c } } -> c"),
+            ("perform e (f x) + 1", "perform e (f x) + 1"),
+            ("[]", "[]"),
+            ("x :: (y :: zs)", "x :: y :: zs"),
+            ("(x :: y) :: z", "(x :: y) :: z"),
+            ("(x + 1 :: tl ys) == [1; (a; b)]", "x + 1 :: tl ys == [1; (a; b)]"),
+            ("[x :: xs; fun x -> x]", "[x :: xs; fun x -> x]"),
+            (
+                "reset (fun () -> 1 + shift (fun k -> k 2))",
+                "reset (fun () -> 1 + shift (fun k -> k 2))",
+            ),
+            ("throw k (callcc f)", "throw k (callcc f)"),
+            ("f (perform e x) (perform e (-1))", "f (perform e x) (perform e (-1))"),
+            ("a || b && c", "a || b && c"),
+            ("(a || b) && c", "(a || b) && c"),
+            ("a && b && c", "a && b && c"),
+            ("(a && b) && c", "(a && b) && c"),
+            ("x < 1 || (x > 2) == y", "x < 1 || (x > 2) == y"),
+            ("(x < 1 || x > 2) == y", "(x < 1 || x > 2) == y"),
+            ("not (a && b) || not c", "not (a && b) || not c"),
+            ("a && (fun x -> x) b", "a && (fun x -> x) b"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(pretty(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_idempotent() {
+        for example in ["fib", "list", "twice", "generators", "state", "nondet"] {
+            let input = std::fs::read_to_string(format!("../examples/{}.ml", example)).unwrap();
+            let once = pretty(&input);
+            assert_eq!(pretty(&once), once);
+        }
+    }
+}
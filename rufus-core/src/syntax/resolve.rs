@@ -0,0 +1,213 @@
+use super::*;
+
+/// An occurrence of a variable together with the span of its binder.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Reference {
+    pub span: Span,
+    /// The span of the binder or `None` if the variable is bound outside of
+    /// the expression, e.g., in the REPL session.
+    pub binder: Option<Span>,
+}
+
+impl Expr {
+    /// Resolve all variables in an indexed expression to their binders.
+    pub fn references(&self) -> Vec<Reference> {
+        let mut refs = Vec::new();
+        self.references_aux(&mut Vec::new(), &mut refs);
+        refs
+    }
+
+    fn references_aux(&self, scope: &mut Vec<Span>, refs: &mut Vec<Reference>) {
+        use Expr::*;
+        match self {
+            Var(_, None, _) => panic!("resolving unindexed variable"),
+            Var(_, Some(index), span) => {
+                let binder = scope.len().checked_sub(*index).map(|i| scope[i]);
+                refs.push(Reference {
+                    span: *span,
+                    binder,
+                });
+            }
+            Lam(ps, e) => {
+                let len = scope.len();
+                scope.extend(ps.iter().flat_map(Pattern::binders).map(|x| x.span));
+                e.references_aux(scope, refs);
+                scope.truncate(len);
+            }
+            Let(p, e1, e2) | Try(e1, p, e2) => {
+                e1.references_aux(scope, refs);
+                let len = scope.len();
+                scope.extend(p.binders().into_iter().map(|x| x.span));
+                e2.references_aux(scope, refs);
+                scope.truncate(len);
+            }
+            LetRec(bindings, e) => {
+                let len = scope.len();
+                scope.extend(bindings.iter().map(|(f, _)| f.span));
+                for (_, e) in bindings {
+                    e.references_aux(scope, refs);
+                }
+                e.references_aux(scope, refs);
+                scope.truncate(len);
+            }
+            Handle(e, clauses) => {
+                e.references_aux(scope, refs);
+                for clause in clauses {
+                    let len = scope.len();
+                    scope.extend(clause.binders().into_iter().map(|x| x.span));
+                    clause.body().references_aux(scope, refs);
+                    scope.truncate(len);
+                }
+            }
+            _ => {
+                for e in self.children() {
+                    e.references_aux(scope, refs);
+                }
+            }
+        }
+    }
+
+    /// Collect all binders in the expression.
+    pub fn binders(&self) -> Vec<&Binder> {
+        let mut binders = Vec::new();
+        self.binders_aux(&mut binders);
+        binders
+    }
+
+    fn binders_aux<'a>(&'a self, binders: &mut Vec<&'a Binder>) {
+        use Expr::*;
+        match self {
+            Lam(ps, _) => binders.extend(ps.iter().flat_map(Pattern::binders)),
+            Let(p, _, _) | Try(_, p, _) => binders.extend(p.binders()),
+            LetRec(bindings, _) => binders.extend(bindings.iter().map(|(f, _)| f)),
+            Handle(_, clauses) => binders.extend(clauses.iter().flat_map(Clause::binders)),
+            _ => {}
+        }
+        for e in self.children() {
+            e.binders_aux(binders);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn resolve(input: &str) -> Vec<(&str, Option<&str>)> {
+        let expr = parse::expr(input).into_result().unwrap().index().unwrap();
+        expr.references()
+            .into_iter()
+            .map(|r| {
+                let binder = r.binder.map(|b| &input[b.start..b.end]);
+                (&input[r.span.start..r.span.end], binder)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let input = "let x = 1 in let f = fun x y -> x + y in f x";
+        let positions: Vec<_> = parse::expr(input)
+            .into_result()
+            .unwrap()
+            .index()
+            .unwrap()
+            .references()
+            .into_iter()
+            .map(|r| (r.span.start, r.binder.map(|b| b.start)))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (32, Some(25)),
+                (36, Some(27)),
+                (41, Some(17)),
+                (43, Some(4))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_let_rec() {
+        let input = "let rec f = fun n -> f n in f";
+        assert_eq!(
+            resolve(input),
+            vec![("f", Some("f")), ("n", Some("n")), ("f", Some("f"))]
+        );
+    }
+
+    #[test]
+    fn test_outer_scope() {
+        let expr = parse::expr("fun x -> y x")
+            .into_result()
+            .unwrap()
+            .index_in(&["y".to_string()])
+            .unwrap();
+        let binders: Vec<_> = expr.references().into_iter().map(|r| r.binder).collect();
+        assert_eq!(binders, vec![None, Some(Span::new(4, 5))]);
+    }
+
+    #[test]
+    fn test_binders() {
+        let input = "let f = fun x y -> x in let rec g = fun z -> z in g";
+        let expr = parse::expr(input).into_result().unwrap();
+        let names: Vec<_> = expr.binders().iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, vec!["f", "x", "y", "g", "z"]);
+    }
+
+    #[test]
+    fn test_record_punning() {
+        let input = "let x = 1 in let { y; z = w } = { y = x; z = x } in { x; y; w }";
+        assert_eq!(
+            resolve(input),
+            vec![
+                ("x", Some("x")),
+                ("x", Some("x")),
+                ("x", Some("x")),
+                ("y", Some("y")),
+                ("w", Some("w"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_let_rec_and() {
+        let input = "let rec f = fun x -> g x and g = fun y -> f y in g";
+        assert_eq!(
+            resolve(input),
+            vec![
+                ("g", Some("g")),
+                ("x", Some("x")),
+                ("f", Some("f")),
+                ("y", Some("y")),
+                ("g", Some("g"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tuple_patterns() {
+        let input = "let (x, (y, z)) = t in fun (a, b) c -> x + y + z + a + b + c";
+        let expr = parse::expr(input)
+            .into_result()
+            .unwrap()
+            .index_in(&["t".to_string()])
+            .unwrap();
+        let binders: Vec<_> = expr
+            .references()
+            .into_iter()
+            .map(|r| r.binder.map(|b| &input[b.start..b.end]))
+            .collect();
+        let expected = vec![
+            None,
+            Some("x"),
+            Some("y"),
+            Some("z"),
+            Some("a"),
+            Some("b"),
+            Some("c"),
+        ];
+        assert_eq!(binders, expected);
+    }
+}
@@ -0,0 +1,233 @@
+use lsp_types::{
+    DiagnosticSeverity, DocumentSymbol, Hover, HoverContents, MarkupContent, MarkupKind, Position,
+    Range, SymbolKind, TextEdit,
+};
+
+use rufus_core::diagnostics::{Diagnostic, Severity};
+use rufus_core::humanizer::{ColumnUnit, Humanizer, SourceLocation};
+use rufus_core::parse;
+use rufus_core::prelude::Prelude;
+use rufus_core::syntax::{Expr, Pattern, Reference, Span};
+
+/// The result of analyzing the source of a document.
+pub struct Document {
+    source: String,
+    humanizer: Humanizer,
+    /// The expression in the document. It is indexed unless indexing failed
+    /// and contains error nodes if there are syntax errors.
+    expr: Option<Expr>,
+    /// Errors from parsing and indexing.
+    errors: Vec<Diagnostic>,
+    has_syntax_errors: bool,
+    /// All variables in the document resolved to their binders. This is
+    /// empty if indexing failed.
+    references: Vec<Reference>,
+}
+
+impl Document {
+    pub fn new(source: String) -> Self {
+        let humanizer = Humanizer::new(&source);
+        let parsed = parse::expr(&source);
+        let mut errors = parsed.diagnostics();
+        let has_syntax_errors = !errors.is_empty();
+        let mut references = Vec::new();
+        // NOTE(MH): We analyze the partial syntax tree even if there are
+        // syntax errors to keep navigation working while typing.
+        let expr = parsed
+            .tree
+            .map(|expr| match expr.clone().index_in(Prelude::get().names()) {
+                Ok(expr) => {
+                    references = expr.references();
+                    expr
+                }
+                Err(err) => {
+                    errors.push(err.into());
+                    expr
+                }
+            });
+        Self {
+            source,
+            humanizer,
+            expr,
+            errors,
+            has_syntax_errors,
+            references,
+        }
+    }
+
+    pub fn diagnostics(&self) -> Vec<lsp_types::Diagnostic> {
+        self.errors
+            .iter()
+            .map(|err| {
+                let mut message = err.message.clone();
+                for note in &err.notes {
+                    message.push('\n');
+                    message.push_str(note);
+                }
+                lsp_types::Diagnostic {
+                    range: self.range(err.primary_span().unwrap_or_default()),
+                    severity: Some(match err.severity {
+                        Severity::Error => DiagnosticSeverity::ERROR,
+                        Severity::Warning => DiagnosticSeverity::WARNING,
+                        Severity::Note => DiagnosticSeverity::INFORMATION,
+                    }),
+                    source: Some("rufus".to_string()),
+                    message,
+                    ..lsp_types::Diagnostic::default()
+                }
+            })
+            .collect()
+    }
+
+    /// Find the binder of the variable at the given position.
+    pub fn definition(&self, pos: Position) -> Option<Range> {
+        let offset = self.offset(pos);
+        self.references
+            .iter()
+            .find(|r| r.span.contains(offset))
+            .and_then(|r| r.binder)
+            .map(|binder| self.range(binder))
+    }
+
+    /// Find all uses of the variable at the given position, which can either
+    /// be a binder or a use itself.
+    pub fn references(&self, pos: Position, include_declaration: bool) -> Vec<Range> {
+        let binder = match self.binder_at(self.offset(pos)) {
+            Some(binder) => binder,
+            None => return Vec::new(),
+        };
+        let mut spans = Vec::new();
+        if include_declaration {
+            spans.push(binder);
+        }
+        spans.extend(
+            self.references
+                .iter()
+                .filter(|r| r.binder == Some(binder))
+                .map(|r| r.span),
+        );
+        spans.into_iter().map(|span| self.range(span)).collect()
+    }
+
+    pub fn hover(&self, pos: Position) -> Option<Hover> {
+        let offset = self.offset(pos);
+        let (span, binder) = match self.references.iter().find(|r| r.span.contains(offset)) {
+            Some(r) => (r.span, r.binder?),
+            None => {
+                let binder = self.binder_at(offset)?;
+                (binder, binder)
+            }
+        };
+        // TODO(MH): Show the type of the variable once we have type inference.
+        let value = format!(
+            "```rufus\n{}\n```\nbound at {}",
+            &self.source[span.start..span.end],
+            self.humanizer.locate(binder.start, ColumnUnit::Char),
+        );
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(self.range(span)),
+        })
+    }
+
+    /// List the top-level `let`s, i.e., the `let`s that are not nested in
+    /// any other expression except `let`s.
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        let mut symbols = Vec::new();
+        let mut expr = match &self.expr {
+            Some(expr) => expr,
+            None => return symbols,
+        };
+        loop {
+            let (binders, body) = match expr {
+                Expr::Let(pattern, bound, body) => {
+                    let kind = match pattern {
+                        Pattern::Var(_) => symbol_kind(bound),
+                        _ => SymbolKind::VARIABLE,
+                    };
+                    let binders = pattern.binders().into_iter().map(|x| (x, kind));
+                    (binders.collect::<Vec<_>>(), body)
+                }
+                Expr::LetRec(bindings, body) => {
+                    let binders = bindings.iter().map(|(f, bound)| (f, symbol_kind(bound)));
+                    (binders.collect(), body)
+                }
+                _ => break,
+            };
+            for (binder, kind) in binders {
+                #[allow(deprecated)]
+                symbols.push(DocumentSymbol {
+                    name: binder.name.clone(),
+                    detail: None,
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    range: self.range(binder.span),
+                    selection_range: self.range(binder.span),
+                    children: None,
+                });
+            }
+            expr = body;
+        }
+        symbols
+    }
+
+    /// Format the whole document. Returns `None` if the document has syntax
+    /// errors or contains comments, which the formatter would drop.
+    pub fn format(&self) -> Option<Vec<TextEdit>> {
+        let expr = self.expr.as_ref()?;
+        if self.has_syntax_errors || self.source.contains("(*") {
+            return None;
+        }
+        let mut new_text = expr.pretty();
+        new_text.push('\n');
+        let range = self.range(Span::new(0, self.source.len()));
+        Some(vec![TextEdit { range, new_text }])
+    }
+
+    fn binder_at(&self, offset: usize) -> Option<Span> {
+        match self.references.iter().find(|r| r.span.contains(offset)) {
+            Some(r) => r.binder,
+            None => self
+                .expr
+                .iter()
+                .flat_map(|expr| expr.binders())
+                .map(|binder| binder.span)
+                .find(|span| span.contains(offset)),
+        }
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range {
+            start: self.position(span.start),
+            end: self.position(span.end),
+        }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let loc = self.humanizer.locate(offset, ColumnUnit::Utf16);
+        Position {
+            line: loc.line,
+            character: loc.column,
+        }
+    }
+
+    fn offset(&self, pos: Position) -> usize {
+        let loc = SourceLocation {
+            line: pos.line,
+            column: pos.character,
+        };
+        self.humanizer.offset_in(loc, ColumnUnit::Utf16)
+    }
+}
+
+/// The kind of symbol a variable bound to the expression is.
+fn symbol_kind(bound: &Expr) -> SymbolKind {
+    match bound {
+        Expr::Lam(..) => SymbolKind::FUNCTION,
+        _ => SymbolKind::VARIABLE,
+    }
+}
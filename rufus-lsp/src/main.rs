@@ -0,0 +1,11 @@
+mod analysis;
+mod server;
+
+use lsp_server::Connection;
+
+fn main() -> server::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    server::run(&connection)?;
+    io_threads.join()?;
+    Ok(())
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest, References, Request as _,
+};
+use lsp_types::{
+    DocumentSymbolResponse, GotoDefinitionResponse, HoverProviderCapability, Location, OneOf,
+    PublishDiagnosticsParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
+    Url,
+};
+
+use crate::analysis::Document;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, Document>,
+}
+
+/// Run the server on the given connection until the client shuts it down.
+pub fn run(connection: &Connection) -> Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server::default();
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    return Ok(());
+                }
+                let resp = server.handle_request(req);
+                connection.sender.send(Message::Response(resp))?;
+            }
+            Message::Notification(not) => {
+                if let Some(params) = server.handle_notification(not)? {
+                    let not = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+                    connection.sender.send(Message::Notification(not))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+impl Server {
+    fn handle_request(&self, req: Request) -> Response {
+        match req.method.as_str() {
+            GotoDefinition::METHOD => self.dispatch::<GotoDefinition>(req, |params| {
+                let params = params.text_document_position_params;
+                let uri = params.text_document.uri;
+                let range = self.documents.get(&uri)?.definition(params.position)?;
+                Some(GotoDefinitionResponse::Scalar(Location { uri, range }))
+            }),
+            References::METHOD => self.dispatch::<References>(req, |params| {
+                let include_declaration = params.context.include_declaration;
+                let params = params.text_document_position;
+                let uri = params.text_document.uri;
+                let ranges = self
+                    .documents
+                    .get(&uri)?
+                    .references(params.position, include_declaration);
+                let locations = ranges
+                    .into_iter()
+                    .map(|range| Location {
+                        uri: uri.clone(),
+                        range,
+                    })
+                    .collect();
+                Some(locations)
+            }),
+            HoverRequest::METHOD => self.dispatch::<HoverRequest>(req, |params| {
+                let params = params.text_document_position_params;
+                self.documents
+                    .get(&params.text_document.uri)?
+                    .hover(params.position)
+            }),
+            DocumentSymbolRequest::METHOD => {
+                self.dispatch::<DocumentSymbolRequest>(req, |params| {
+                    let document = self.documents.get(&params.text_document.uri)?;
+                    Some(DocumentSymbolResponse::Nested(document.symbols()))
+                })
+            }
+            Formatting::METHOD => self.dispatch::<Formatting>(req, |params| {
+                self.documents.get(&params.text_document.uri)?.format()
+            }),
+            _ => Response::new_err(
+                req.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unknown method: {}", req.method),
+            ),
+        }
+    }
+
+    fn dispatch<R: lsp_types::request::Request>(
+        &self,
+        req: Request,
+        handler: impl FnOnce(R::Params) -> R::Result,
+    ) -> Response {
+        match serde_json::from_value(req.params) {
+            Ok(params) => Response::new_ok(req.id, handler(params)),
+            Err(err) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    /// Handle a notification. Returns the diagnostics to publish if a
+    /// document has changed.
+    fn handle_notification(
+        &mut self,
+        not: Notification,
+    ) -> Result<Option<PublishDiagnosticsParams>> {
+        let (uri, version) = match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                let document = params.text_document;
+                self.documents
+                    .insert(document.uri.clone(), Document::new(document.text));
+                (document.uri, Some(document.version))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                let document = params.text_document;
+                // NOTE(MH): We only support full syncs, so the last change
+                // contains the whole document.
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents
+                        .insert(document.uri.clone(), Document::new(change.text));
+                }
+                (document.uri, Some(document.version))
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(not.params)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                return Ok(Some(PublishDiagnosticsParams::new(uri, Vec::new(), None)));
+            }
+            _ => return Ok(None),
+        };
+        let diagnostics = self
+            .documents
+            .get(&uri)
+            .map_or_else(Vec::new, Document::diagnostics);
+        Ok(Some(PublishDiagnosticsParams::new(
+            uri,
+            diagnostics,
+            version,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::RequestId;
+    use lsp_types::notification::{Exit, Initialized};
+    use lsp_types::request::{Initialize, Shutdown};
+    use lsp_types::*;
+    use std::thread::JoinHandle;
+
+    const URI: &str = "file:///test.ml";
+
+    /// An in-process client talking to a server running in another thread.
+    struct Client {
+        connection: Connection,
+        server: JoinHandle<()>,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (server, connection) = Connection::memory();
+            let server = std::thread::spawn(move || run(&server).unwrap());
+            let mut client = Self {
+                connection,
+                server,
+                next_id: 0,
+            };
+            client.request::<Initialize>(InitializeParams::default());
+            client.notify::<Initialized>(InitializedParams {});
+            client
+        }
+
+        fn request<R: request::Request>(&mut self, params: R::Params) -> R::Result {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            let req = Request::new(id.clone(), R::METHOD.to_string(), params);
+            self.connection.sender.send(Message::Request(req)).unwrap();
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(resp) => {
+                    assert_eq!(resp.id, id);
+                    serde_json::from_value(resp.result.unwrap()).unwrap()
+                }
+                msg => panic!("expected response, got {:?}", msg),
+            }
+        }
+
+        fn notify<N: notification::Notification>(&self, params: N::Params) {
+            let not = Notification::new(N::METHOD.to_string(), params);
+            self.connection
+                .sender
+                .send(Message::Notification(not))
+                .unwrap();
+        }
+
+        /// Open a document and return the diagnostics published for it.
+        fn open(&self, text: &str) -> Vec<Diagnostic> {
+            self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(
+                    Url::parse(URI).unwrap(),
+                    "rufus".to_string(),
+                    1,
+                    text.to_string(),
+                ),
+            });
+            match self.connection.receiver.recv().unwrap() {
+                Message::Notification(not) => {
+                    assert_eq!(not.method, PublishDiagnostics::METHOD);
+                    let params: PublishDiagnosticsParams =
+                        serde_json::from_value(not.params).unwrap();
+                    params.diagnostics
+                }
+                msg => panic!("expected notification, got {:?}", msg),
+            }
+        }
+
+        fn shutdown(mut self) {
+            self.request::<Shutdown>(());
+            self.notify::<Exit>(());
+            self.server.join().unwrap();
+        }
+    }
+
+    fn position(line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(Url::parse(URI).unwrap()),
+            Position::new(line, character),
+        )
+    }
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let client = Client::start();
+        let diagnostics = client.open("let x = 1 in\nx + y");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range, range(1, 4, 5));
+        assert_eq!(diagnostics[0].message, "unbound variable: y");
+
+        let diagnostics = client.open("let x = 1 in\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range, range(0, 12, 12));
+        assert!(diagnostics[0]
+            .message
+            .starts_with("unexpected end of input"));
+
+        let diagnostics = client.open("let x = (1 +) in\nlet y = { a = } in\nx");
+        let ranges: Vec<_> = diagnostics.iter().map(|d| d.range).collect();
+        assert_eq!(ranges, vec![range(0, 12, 13), range(1, 14, 15)]);
+
+        // NOTE(MH): `𝔘` is two UTF-16 code units long.
+        let diagnostics = client.open("let x = 1 in\r\nx + (* 𝔘 *) y");
+        assert_eq!(diagnostics[0].range, range(1, 13, 14));
+
+        assert_eq!(client.open("let x = 1 in x"), vec![]);
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut client = Client::start();
+        client.open("let f = fun x ->\n  x + x\nin\nf 1");
+
+        let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: position(1, 6),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let location = Location::new(Url::parse(URI).unwrap(), range(0, 12, 13));
+        assert_eq!(definition, Some(GotoDefinitionResponse::Scalar(location)));
+
+        let references = client.request::<References>(ReferenceParams {
+            text_document_position: position(0, 12),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let ranges: Vec<_> = references.unwrap().into_iter().map(|l| l.range).collect();
+        assert_eq!(
+            ranges,
+            vec![range(0, 12, 13), range(1, 2, 3), range(1, 6, 7)]
+        );
+
+        let hover = client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: position(3, 0),
+            work_done_progress_params: Default::default(),
+        });
+        match hover.unwrap().contents {
+            HoverContents::Markup(content) => {
+                assert_eq!(content.value, "```rufus\nf\n```\nbound at 1:5")
+            }
+            contents => panic!("unexpected hover contents: {:?}", contents),
+        }
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut client = Client::start();
+        client.open("let x = 1 in\nlet rec f = fun n -> f n in\nlet g = fun y -> y in\ng x");
+        let symbols = client.request::<DocumentSymbolRequest>(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier::new(Url::parse(URI).unwrap()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let symbols = match symbols {
+            Some(DocumentSymbolResponse::Nested(symbols)) => symbols,
+            symbols => panic!("unexpected symbols: {:?}", symbols),
+        };
+        let symbols: Vec<_> = symbols.iter().map(|s| (s.name.as_str(), s.kind)).collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("x", SymbolKind::VARIABLE),
+                ("f", SymbolKind::FUNCTION),
+                ("g", SymbolKind::FUNCTION)
+            ]
+        );
+        client.shutdown();
+    }
+
+    #[test]
+    fn test_formatting() {
+        let mut client = Client::start();
+        let params = || DocumentFormattingParams {
+            text_document: TextDocumentIdentifier::new(Url::parse(URI).unwrap()),
+            options: FormattingOptions::default(),
+            work_done_progress_params: Default::default(),
+        };
+
+        client.open("let x=1 in   x+x");
+        let edits = client.request::<Formatting>(params()).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range, range(0, 0, 16));
+        assert_eq!(edits[0].new_text, "let x = 1 in\nx + x\n");
+
+        client.open("(* keep me *) 1+1");
+        assert_eq!(client.request::<Formatting>(params()), None);
+        client.shutdown();
+    }
+}
@@ -0,0 +1,311 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hinter, HistoryHinter};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+
+use rufus_core::lexer::{Lexer, LexicalErrorKind, TokenClass, KEYWORDS};
+use rufus_core::syntax::Name;
+
+use crate::COMMANDS;
+
+const KEYWORD_STYLE: &str = "\x1b[1;35m";
+const NUMBER_STYLE: &str = "\x1b[33m";
+const COMMENT_STYLE: &str = "\x1b[2m";
+const BRACKET_STYLE: &str = "\x1b[1;34m";
+const HINT_STYLE: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// The rustyline helper of the REPL. It completes keywords, commands and the
+/// names bound in the session, highlights the input, checks that brackets
+/// are balanced and shows hints from the history.
+pub struct ReplHelper {
+    hinter: HistoryHinter,
+    /// The names bound in the session.
+    pub names: Vec<Name>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Class {
+    Keyword,
+    Number,
+    Comment,
+    Bracket,
+    Other,
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        Self {
+            hinter: HistoryHinter {},
+            names: Vec::new(),
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let prefix = &line[start..pos];
+        let mut candidates: Vec<&str> = if start == 1 && line.starts_with(':') {
+            COMMANDS.to_vec()
+        } else if prefix.is_empty() {
+            Vec::new()
+        } else {
+            KEYWORDS
+                .iter()
+                .copied()
+                .chain(self.names.iter().map(String::as_str))
+                .collect()
+        };
+        candidates.retain(|candidate| candidate.starts_with(prefix));
+        candidates.sort_unstable();
+        candidates.dedup();
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.to_string(),
+                replacement: candidate.to_string(),
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        self.hinter.hint(line, pos, ctx)
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        let tokens = classify(line);
+        let matching = matching_bracket(&tokens, line, pos);
+        let mut highlighted = String::with_capacity(2 * line.len());
+        let mut last = 0;
+        for (range, class) in tokens {
+            highlighted.push_str(&line[last..range.start]);
+            let style = match class {
+                Class::Keyword => Some(KEYWORD_STYLE),
+                Class::Number => Some(NUMBER_STYLE),
+                Class::Comment => Some(COMMENT_STYLE),
+                Class::Bracket if Some(range.start) == matching => Some(BRACKET_STYLE),
+                Class::Bracket | Class::Other => None,
+            };
+            match style {
+                Some(style) => {
+                    highlighted.push_str(style);
+                    highlighted.push_str(&line[range.clone()]);
+                    highlighted.push_str(RESET);
+                }
+                None => highlighted.push_str(&line[range.clone()]),
+            }
+            last = range.end;
+        }
+        highlighted.push_str(&line[last..]);
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("{}{}{}", HINT_STYLE, hint, RESET))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // NOTE(MH): Moving the cursor can change which bracket is matched.
+        true
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(validate_brackets(ctx.input()))
+    }
+}
+
+/// Split a line into the tokens relevant for highlighting. Whitespace is not
+/// part of any token. An unterminated comment extends to the end of the line.
+fn classify(line: &str) -> Vec<(Range<usize>, Class)> {
+    Lexer::new(line)
+        .with_comments()
+        .map(|result| match result {
+            Ok((start, token, end)) => {
+                let class = match token.class() {
+                    TokenClass::Keyword => Class::Keyword,
+                    TokenClass::Number => Class::Number,
+                    TokenClass::Comment => Class::Comment,
+                    TokenClass::Bracket => Class::Bracket,
+                    TokenClass::Identifier | TokenClass::String | TokenClass::Operator => {
+                        Class::Other
+                    }
+                };
+                (start..end, class)
+            }
+            Err(err) => match err.kind {
+                LexicalErrorKind::UnterminatedComment => {
+                    (err.span.start..line.len(), Class::Comment)
+                }
+                _ => (err.span.start..err.span.end, Class::Other),
+            },
+        })
+        .collect()
+}
+
+/// Find the position of the bracket matching the one at or right before the
+/// cursor.
+fn matching_bracket(tokens: &[(Range<usize>, Class)], line: &str, pos: usize) -> Option<usize> {
+    let brackets: Vec<(usize, u8)> = tokens
+        .iter()
+        .filter(|(_, class)| *class == Class::Bracket)
+        .map(|(range, _)| (range.start, line.as_bytes()[range.start]))
+        .collect();
+    let index = brackets
+        .iter()
+        .position(|(i, _)| *i == pos)
+        .or_else(|| brackets.iter().position(|(i, _)| *i + 1 == pos))?;
+    let (_, bracket) = brackets[index];
+    let mut depth = 0;
+    if is_opening(bracket) {
+        for (i, b) in &brackets[index..] {
+            depth += if is_opening(*b) { 1 } else { -1 };
+            if depth == 0 {
+                return Some(*i);
+            }
+        }
+    } else {
+        for (i, b) in brackets[..=index].iter().rev() {
+            depth += if is_opening(*b) { -1 } else { 1 };
+            if depth == 0 {
+                return Some(*i);
+            }
+        }
+    }
+    None
+}
+
+fn is_opening(bracket: u8) -> bool {
+    matches!(bracket, b'(' | b'{' | b'[')
+}
+
+/// Check that all brackets in the input are balanced. Open brackets and
+/// unterminated comments mean the input is incomplete.
+fn validate_brackets(input: &str) -> ValidationResult {
+    let unterminated_comment = Lexer::new(input).any(
+        |result| matches!(result, Err(err) if err.kind == LexicalErrorKind::UnterminatedComment),
+    );
+    if unterminated_comment {
+        return ValidationResult::Incomplete;
+    }
+    let mut stack = Vec::new();
+    for (range, class) in classify(input) {
+        if class != Class::Bracket {
+            continue;
+        }
+        let bracket = input.as_bytes()[range.start];
+        match bracket {
+            b'(' | b'{' | b'[' => stack.push(bracket),
+            _ => {
+                let expected = match bracket {
+                    b')' => b'(',
+                    b']' => b'[',
+                    _ => b'{',
+                };
+                if stack.pop() != Some(expected) {
+                    return ValidationResult::Invalid(Some(format!(
+                        " - unmatched `{}`",
+                        bracket as char
+                    )));
+                }
+            }
+        }
+    }
+    if stack.is_empty() {
+        ValidationResult::Valid(None)
+    } else {
+        ValidationResult::Incomplete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        use Class::*;
+        let line = "let x = (* one *) f(12) in";
+        let classes: Vec<_> = classify(line)
+            .into_iter()
+            .map(|(range, class)| (&line[range], class))
+            .collect();
+        assert_eq!(
+            classes,
+            vec![
+                ("let", Keyword),
+                ("x", Other),
+                ("=", Other),
+                ("(* one *)", Comment),
+                ("f", Other),
+                ("(", Bracket),
+                ("12", Number),
+                (")", Bracket),
+                ("in", Keyword),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        let line = "f ({ x = (1) })";
+        let tokens = classify(line);
+        assert_eq!(matching_bracket(&tokens, line, 2), Some(14));
+        assert_eq!(matching_bracket(&tokens, line, 15), Some(2));
+        assert_eq!(matching_bracket(&tokens, line, 3), Some(13));
+        assert_eq!(matching_bracket(&tokens, line, 10), Some(11));
+        assert_eq!(matching_bracket(&tokens, line, 12), Some(9));
+        assert_eq!(matching_bracket(&tokens, line, 5), None);
+        let line = "hd [(1); 2]";
+        let tokens = classify(line);
+        assert_eq!(matching_bracket(&tokens, line, 3), Some(10));
+        assert_eq!(matching_bracket(&tokens, line, 11), Some(3));
+        assert_eq!(matching_bracket(&tokens, line, 4), Some(6));
+    }
+
+    #[test]
+    fn test_validate_brackets() {
+        let valid = |input| matches!(validate_brackets(input), ValidationResult::Valid(_));
+        let incomplete = |input| matches!(validate_brackets(input), ValidationResult::Incomplete);
+        let invalid = |input| matches!(validate_brackets(input), ValidationResult::Invalid(_));
+        assert!(valid("f (g { x = 1 })"));
+        assert!(valid("(* ( *) 1"));
+        assert!(incomplete("f (g"));
+        assert!(incomplete("{ x = (1"));
+        assert!(incomplete("1 (* comment"));
+        assert!(incomplete("1 (* (* nested *)"));
+        assert!(valid("1 (* (* nested *) *)"));
+        assert!(invalid("f (g })"));
+        assert!(valid("[1; 2; 3]"));
+        assert!(valid("hd [1]"));
+        assert!(valid("[(1, 2); ({ x = [] }, 3)]"));
+        assert!(incomplete("[1; (2"));
+        assert!(invalid("[1)"));
+        assert!(invalid("(1]"));
+        assert!(invalid("1)"));
+    }
+}
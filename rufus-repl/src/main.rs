@@ -1,37 +1,95 @@
-use rufus_core::{cek, parser, syntax};
+mod helper;
+mod session;
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use rufus_core::diagnostics::Diagnostic;
+use rufus_core::parse;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-use syntax::Expr;
+use helper::ReplHelper;
+use rufus_core::syntax::{Expr, Input};
+use session::Session;
+use typed_arena::Arena;
 
 const HISTORY_FILE: &str = ".rufus_history";
 
+const HELP: &str = "\
+<expr>              evaluate an expression
+let <name> = <expr> add a binding to the session
+:type <expr>        evaluate an expression without changing the session and
+                    show the runtime type of its value
+:ast <expr>         show the parsed and the indexed syntax tree of an expression
+:steps <expr>       evaluate an expression and count the machine transitions
+:time <expr>        evaluate an expression and measure the time it takes
+:list               list all bindings in the session
+:forget <name>...   remove bindings from the session
+:load <file>        load the definitions of a file into the session
+:reload             load the last loaded file again
+:help               show this help";
+
+/// The names of all REPL commands, used for completion.
+const COMMANDS: &[&str] = &[
+    "ast", "forget", "help", "list", "load", "reload", "steps", "time", "type",
+];
+
+struct Repl<'a> {
+    session: Session<'a>,
+    /// The file loaded by the last `:load` command.
+    loaded: Option<PathBuf>,
+}
+
 fn main() {
     println!("Hello!");
-    // `()` can be used when no completer is required
-    let mut rl = Editor::<()>::new();
+    let mut rl = Editor::<ReplHelper>::new();
+    rl.set_helper(Some(ReplHelper::new()));
     if rl.load_history(HISTORY_FILE).is_err() {
         println!("No previous history.");
     }
-    let parser = parser::ExprParser::new();
+    // NOTE(MH): This is the only option, so we do without an argument parser.
+    let prelude = !std::env::args().skip(1).any(|arg| arg == "--no-prelude");
+    let exprs = Arena::new();
+    let mut repl = Repl::new(&exprs, prelude);
+    rl.helper_mut().unwrap().names = repl.session.names();
+    let mut input = String::new();
 
     loop {
-        let readline = rl.readline("> ");
+        let prompt = if input.is_empty() { "> " } else { "| " };
+        let readline = rl.readline(prompt);
         match readline {
             Ok(line) => {
-                rl.add_history_entry(line.as_str());
-                match parser
-                    .parse(&line)
-                    .map_err(|err| lalrpop_util::ParseError::to_string(&err))
-                    .and_then(Expr::index)
-                    .and_then(|expr| {
-                        let machine = cek::Machine::new(&expr);
-                        machine.run().map(|value| format!("{:?}", value))
-                    }) {
-                    Ok(value) => println!("{}", value),
-                    Err(err) => println!("Error: {}", err),
+                if input.is_empty() {
+                    if let Some(command) = line.trim().strip_prefix(':') {
+                        rl.add_history_entry(line.as_str());
+                        repl.run_command(command);
+                        rl.helper_mut().unwrap().names = repl.session.names();
+                        continue;
+                    }
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                } else {
+                    input.push('\n');
                 }
+                input.push_str(&line);
+                let parsed = parse::input(&input);
+                // NOTE(MH): The input is incomplete, keep reading.
+                if parsed.is_incomplete() {
+                    continue;
+                }
+                rl.add_history_entry(input.as_str());
+                match parsed.into_result() {
+                    Ok(parsed) => {
+                        repl.run_input(&input, parsed);
+                        rl.helper_mut().unwrap().names = repl.session.names();
+                    }
+                    Err(errors) => println!("{}", render_all(&errors, &input, None)),
+                }
+                input.clear();
             }
+            Err(ReadlineError::Interrupted) if !input.is_empty() => input.clear(),
             Err(ReadlineError::Interrupted) => break,
             Err(ReadlineError::Eof) => {
                 println!("Good bye!");
@@ -45,3 +103,144 @@ fn main() {
     }
     rl.save_history(HISTORY_FILE).unwrap();
 }
+
+impl<'a> Repl<'a> {
+    fn new(exprs: &'a Arena<Expr>, prelude: bool) -> Self {
+        Self {
+            session: if prelude {
+                Session::with_prelude(exprs)
+            } else {
+                Session::new(exprs)
+            },
+            loaded: None,
+        }
+    }
+
+    fn run_input(&mut self, source: &str, input: Input) {
+        let result = match input {
+            Input::Decl(decl) => self.session.declare(decl).map(|bindings| {
+                let lines: Vec<_> = bindings
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                lines.join("\n")
+            }),
+            Input::Expr(expr) => self.session.eval(expr).map(|value| format!("{:?}", value)),
+        };
+        match result {
+            Ok(output) => println!("{}", output),
+            Err(err) => println!("{}", err.render(source, None)),
+        }
+    }
+
+    /// Run a REPL command, i.e., an input line starting with `:`.
+    fn run_command(&mut self, command: &str) {
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, arg)| (name, arg.trim()));
+        match name {
+            "help" => println!("{}", HELP),
+            "type" | "ast" | "steps" | "time" if arg.is_empty() => {
+                println!("Error: usage: :{} <expr>", name)
+            }
+            "type" | "ast" | "steps" | "time" => {
+                if let Err(errors) = self.inspect(name, arg) {
+                    println!("{}", render_all(&errors, arg, None));
+                }
+            }
+            "list" => {
+                for (name, value) in self.session.bindings() {
+                    println!("{} = {}", name, value);
+                }
+            }
+            "forget" if !arg.is_empty() => {
+                for name in arg.split_whitespace() {
+                    if !self.session.forget(name) {
+                        println!("Error: unbound variable: {}", name);
+                    }
+                }
+            }
+            "forget" => println!("Error: usage: :forget <name>..."),
+            "load" if !arg.is_empty() => self.load(PathBuf::from(arg)),
+            "load" => println!("Error: usage: :load <file>"),
+            "reload" => match self.loaded.clone() {
+                Some(path) => self.load(path),
+                None => println!("Error: no file has been loaded yet"),
+            },
+            _ => println!("Error: unknown command: :{}", name),
+        }
+    }
+
+    /// Run one of the commands inspecting an expression.
+    fn inspect(&mut self, command: &str, arg: &str) -> Result<(), Vec<Diagnostic>> {
+        let expr = parse::expr(arg).into_result()?;
+        match command {
+            "type" => {
+                let type_name = self.session.type_name(expr).map_err(|err| vec![err])?;
+                println!("{}", type_name);
+            }
+            "ast" => {
+                println!("{:#?}", expr);
+                println!("{:#?}", self.session.index(expr).map_err(|err| vec![err])?);
+            }
+            "steps" => {
+                let (value, steps) = self
+                    .session
+                    .eval_counting_steps(expr)
+                    .map_err(|err| vec![err])?;
+                println!("{:?}", value);
+                println!("{} steps", steps);
+            }
+            "time" => {
+                let start = Instant::now();
+                let value = self.session.eval(expr).map_err(|err| vec![err])?;
+                let elapsed = start.elapsed();
+                println!("{:?}", value);
+                println!("{:?}", elapsed);
+            }
+            _ => unreachable!("unknown inspection command: {}", command),
+        }
+        Ok(())
+    }
+
+    /// Load the definitions of a file into the session. If the file is an
+    /// expression, its leading `let`s become definitions and the remaining
+    /// body is evaluated.
+    fn load(&mut self, path: PathBuf) {
+        match self.load_file(&path) {
+            Ok(names) => println!("Loaded {}: {}", path.display(), names.join(", ")),
+            Err(err) => println!("{}", err),
+        }
+        self.loaded = Some(path);
+    }
+
+    /// Returns the names of the loaded definitions or the error to print.
+    fn load_file(&mut self, path: &Path) -> Result<Vec<String>, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| format!("Error: {}: {}", path.display(), err))?;
+        let name = path.display().to_string();
+        let render = |err: Diagnostic| err.render(&source, Some(&name));
+        let (decls, body) = match parse::input(&source).into_result() {
+            Ok(Input::Decl(decl)) => (vec![decl], None),
+            Ok(Input::Expr(expr)) => {
+                let (decls, body) = expr.into_decls();
+                (decls, Some(body))
+            }
+            Err(errors) => return Err(render_all(&errors, &source, Some(&name))),
+        };
+        let mut names = Vec::new();
+        for decl in decls {
+            let bindings = self.session.declare(decl).map_err(render)?;
+            names.extend(bindings.into_iter().map(|(name, _)| name));
+        }
+        if let Some(body) = body {
+            println!("{:?}", self.session.eval(body).map_err(render)?);
+        }
+        Ok(names)
+    }
+}
+
+fn render_all(errors: &[Diagnostic], source: &str, name: Option<&str>) -> String {
+    let rendered: Vec<_> = errors.iter().map(|err| err.render(source, name)).collect();
+    rendered.join("\n\n")
+}
@@ -0,0 +1,347 @@
+use std::rc::Rc;
+
+use rufus_core::cek::{self, Env, Machine, Store, Value};
+use rufus_core::diagnostics::Diagnostic;
+use rufus_core::prelude::Prelude;
+use rufus_core::syntax::{Decl, Expr, Name};
+use typed_arena::Arena;
+
+/// The bindings introduced by top-level declarations in the REPL. Later
+/// inputs are indexed and evaluated in the scope of these bindings and with
+/// the reference cells allocated by earlier inputs.
+pub struct Session<'a> {
+    /// The indexed expressions of all evaluated inputs. Values, closures in
+    /// particular, keep references into the expression they were computed
+    /// from and can be kept in the session's bindings or store. Thus, the
+    /// expressions need to live as long as the session.
+    exprs: &'a Arena<Expr>,
+    /// The names and values of the prelude's bindings, which are in scope
+    /// below the session's own bindings. Both are empty without a prelude.
+    prelude_names: &'static [Name],
+    prelude: Env<'a>,
+    bindings: Vec<(Name, Rc<Value<'a>>)>,
+    store: Store<'a>,
+}
+
+impl<'a> Session<'a> {
+    /// Create a session without the prelude.
+    pub fn new(exprs: &'a Arena<Expr>) -> Self {
+        Self {
+            exprs,
+            prelude_names: &[],
+            prelude: Env::new(),
+            bindings: Vec::new(),
+            store: Store::new(),
+        }
+    }
+
+    pub fn with_prelude(exprs: &'a Arena<Expr>) -> Self {
+        let prelude = Prelude::get();
+        let (env, store) = prelude.scope();
+        Self {
+            prelude_names: prelude.names(),
+            prelude: env,
+            store,
+            ..Self::new(exprs)
+        }
+    }
+
+    /// The session's own bindings, excluding the prelude.
+    pub fn bindings(&self) -> impl Iterator<Item = (&Name, &Rc<Value<'a>>)> {
+        self.bindings.iter().map(|(name, value)| (name, value))
+    }
+
+    /// Evaluate an expression in the scope of the session's bindings.
+    pub fn eval(&mut self, expr: Expr) -> Result<Rc<Value<'a>>, Diagnostic> {
+        self.eval_counting_steps(expr).map(|(value, _)| value)
+    }
+
+    /// Like `eval` but also return the number of machine transitions.
+    pub fn eval_counting_steps(&mut self, expr: Expr) -> Result<(Rc<Value<'a>>, u64), Diagnostic> {
+        let expr = self.exprs.alloc(self.index(expr)?);
+        let store = std::mem::take(&mut self.store);
+        let mut machine = Machine::new(expr).with_env(self.env()).with_store(store);
+        let result = machine
+            .run()
+            .map_err(|message| Diagnostic::from_runtime_error(message, machine.error_span()));
+        self.store = machine.take_store();
+        Ok((result?, machine.steps()))
+    }
+
+    /// Evaluate an expression like `eval` and return the name of its value's
+    /// type. The expression runs on a copy of the store, so it cannot change
+    /// the session, but it does run: it can fail or even not terminate.
+    pub fn type_name(&self, expr: Expr) -> Result<String, Diagnostic> {
+        let expr = self.index(expr)?;
+        let mut machine = Machine::new(&expr)
+            .with_env(self.env())
+            .with_store(self.store.clone());
+        let result = machine.run();
+        result
+            .map(|value| value.type_name())
+            .map_err(|message| Diagnostic::from_runtime_error(message, machine.error_span()))
+    }
+
+    /// The values of the prelude's and the session's bindings.
+    fn env(&self) -> Env<'a> {
+        let mut env = self.prelude.clone();
+        for (_, value) in &self.bindings {
+            env.push(Rc::clone(value));
+        }
+        env
+    }
+
+    /// Index an expression in the scope of the session's bindings.
+    pub fn index(&self, expr: Expr) -> Result<Expr, Diagnostic> {
+        Ok(expr.index_in(&self.names())?)
+    }
+
+    /// The names in scope, including those of the prelude.
+    pub fn names(&self) -> Vec<Name> {
+        let names = self.bindings.iter().map(|(name, _)| name);
+        self.prelude_names.iter().chain(names).cloned().collect()
+    }
+
+    /// Evaluate a declaration and add its bindings to the session. Returns the
+    /// names and values of the new bindings.
+    pub fn declare(&mut self, decl: Decl) -> Result<Vec<(Name, Rc<Value<'a>>)>, Diagnostic> {
+        let (pattern, expr) = decl.into_binding();
+        let value = self.eval(expr)?;
+        let values = cek::bind(&pattern, value).map_err(Diagnostic::error)?;
+        let names = pattern
+            .binders()
+            .into_iter()
+            .map(|binder| binder.name.clone());
+        let bindings: Vec<_> = names.zip(values).collect();
+        for (name, value) in &bindings {
+            self.forget(name);
+            self.bindings.push((name.clone(), Rc::clone(value)));
+        }
+        Ok(bindings)
+    }
+
+    /// Remove a binding from the session. Returns whether there was one.
+    pub fn forget(&mut self, name: &str) -> bool {
+        let old_len = self.bindings.len();
+        self.bindings.retain(|(other, _)| other != name);
+        self.bindings.len() != old_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rufus_core::parse;
+    use rufus_core::syntax::{Input, Span};
+
+    fn exec(session: &mut Session, input: &str) -> Result<String, String> {
+        let parsed = parse::input(input).into_result();
+        match parsed.map_err(|errors| errors[0].message.clone())? {
+            Input::Decl(decl) => session.declare(decl).map(|bindings| {
+                let values: Vec<_> = bindings
+                    .iter()
+                    .map(|(_, value)| value.to_string())
+                    .collect();
+                values.join(", ")
+            }),
+            Input::Expr(expr) => session.eval(expr).map(|value| value.to_string()),
+        }
+        .map_err(|err| err.message)
+    }
+
+    #[test]
+    fn bindings_persist() {
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        assert_eq!(exec(&mut session, "let x = 2").unwrap(), "2");
+        exec(&mut session, "let f = fun y -> x * y").unwrap();
+        exec(
+            &mut session,
+            "let rec fac = fun n -> if n <= 1 then 1 else n * fac (n-1)",
+        )
+        .unwrap();
+        assert_eq!(exec(&mut session, "f (fac 4)").unwrap(), "48");
+    }
+
+    #[test]
+    fn shadowing() {
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        exec(&mut session, "let x = 1").unwrap();
+        exec(&mut session, "let f = fun y -> x + y").unwrap();
+        exec(&mut session, "let x = 10").unwrap();
+        assert_eq!(exec(&mut session, "f x").unwrap(), "11");
+        let names: Vec<_> = session.bindings().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["f", "x"]);
+    }
+
+    #[test]
+    fn destructuring() {
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        assert_eq!(
+            exec(&mut session, "let (x, (y, z)) = (1, (2, 3))").unwrap(),
+            "1, 2, 3"
+        );
+        assert_eq!(exec(&mut session, "x + y + z").unwrap(), "6");
+        assert_eq!(
+            exec(&mut session, "let (a, b) = (1, 2, 3)").unwrap_err(),
+            "expected tuple of size 2, found Tuple([Num(1), Num(2), Num(3)])"
+        );
+        assert_eq!(exec(&mut session, "a").unwrap_err(), "unbound variable: a");
+        assert_eq!(
+            exec(
+                &mut session,
+                "let { a; b = (c, d) } = { a = 1; b = (2, 3) }"
+            )
+            .unwrap(),
+            "1, 2, 3"
+        );
+        assert_eq!(exec(&mut session, "{ a; c }.c").unwrap(), "2");
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        assert_eq!(
+            exec(
+                &mut session,
+                "let rec even = fun n -> n == 0 || odd (n - 1) and odd = fun n -> n != 0 && even (n - 1)"
+            )
+            .unwrap(),
+            "<PAP>, <PAP>"
+        );
+        assert_eq!(exec(&mut session, "even 4").unwrap(), "true");
+        assert_eq!(exec(&mut session, "odd 4").unwrap(), "false");
+    }
+
+    #[test]
+    fn references_persist() {
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        assert_eq!(exec(&mut session, "let r = ref 1").unwrap(), "<ref 0>");
+        assert_eq!(exec(&mut session, "r := !r + 1").unwrap(), "()");
+        exec(&mut session, "let incr = fun () -> r := !r * 10").unwrap();
+        exec(&mut session, "incr ()").unwrap();
+        assert_eq!(exec(&mut session, "!r").unwrap(), "20");
+        assert_eq!(
+            exec(&mut session, "r := 5; 1 / 0").unwrap_err(),
+            "division by zero"
+        );
+        assert_eq!(exec(&mut session, "!r").unwrap(), "5");
+    }
+
+    #[test]
+    fn forget() {
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        exec(&mut session, "let x = 1").unwrap();
+        exec(&mut session, "let y = x + 1").unwrap();
+        assert!(session.forget("x"));
+        assert!(!session.forget("x"));
+        assert_eq!(exec(&mut session, "y").unwrap(), "2");
+        assert_eq!(exec(&mut session, "x").unwrap_err(), "unbound variable: x");
+    }
+
+    #[test]
+    fn prelude() {
+        let exprs = Arena::new();
+        let mut session = Session::with_prelude(&exprs);
+        assert_eq!(
+            exec(&mut session, "let xs = range 1 4").unwrap(),
+            "[1; 2; 3]"
+        );
+        assert_eq!(
+            exec(&mut session, "let id = fun x -> x + 1").unwrap(),
+            "<PAP>"
+        );
+        assert_eq!(exec(&mut session, "map id xs").unwrap(), "[2; 3; 4]");
+        let names: Vec<_> = session.bindings().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["xs", "id"]);
+        assert!(session.names().contains(&"foldr".to_string()));
+        assert!(!session.forget("map"));
+        assert!(session.forget("id"));
+        assert_eq!(exec(&mut session, "id 1").unwrap(), "1");
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        assert_eq!(
+            exec(&mut session, "range 1 4").unwrap_err(),
+            "unbound variable: range"
+        );
+    }
+
+    #[test]
+    fn runtime_error_spans() {
+        fn error(session: &mut Session, input: &str) -> Diagnostic {
+            session
+                .eval(parse::expr(input).into_result().unwrap())
+                .unwrap_err()
+        }
+        let exprs = Arena::new();
+        let mut session = Session::with_prelude(&exprs);
+        let err = error(&mut session, "nth [] 0");
+        assert_eq!(err.message, "hd of empty list");
+        assert_eq!(err.primary_span(), None);
+        exec(&mut session, "let f = fun x -> x / 0").unwrap();
+        assert_eq!(error(&mut session, "f 1").primary_span(), None);
+        assert_eq!(
+            error(&mut session, "1 + true").primary_span(),
+            Some(Span::new(2, 3))
+        );
+    }
+
+    #[test]
+    fn declare_let_spine() {
+        let exprs = Arena::new();
+        let mut session = Session::with_prelude(&exprs);
+        let source = std::fs::read_to_string("../examples/list.ml").unwrap();
+        let expr = match parse::input(&source).into_result().unwrap() {
+            Input::Expr(expr) => expr,
+            Input::Decl(_) => panic!("expected expression"),
+        };
+        let (decls, body) = expr.into_decls();
+        assert_eq!(decls.len(), 2);
+        for decl in decls {
+            session.declare(decl).unwrap();
+        }
+        assert_eq!(session.eval(body).unwrap().to_string(), "55");
+        assert_eq!(
+            exec(&mut session, "sum (map square (range 0 4))").unwrap(),
+            "14"
+        );
+        assert_eq!(
+            exec(&mut session, "map square (range 0 4)").unwrap(),
+            "[0; 1; 4; 9]"
+        );
+    }
+
+    #[test]
+    fn type_name() {
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        exec(&mut session, "let r = ref 1").unwrap();
+        let type_name = |session: &Session, input| {
+            let expr = parse::expr(input).into_result().unwrap();
+            session.type_name(expr).map_err(|err| err.message)
+        };
+        assert_eq!(type_name(&session, "r := 5").unwrap(), "unit");
+        assert_eq!(type_name(&session, "ref 2").unwrap(), "ref");
+        assert_eq!(
+            type_name(&session, "r := 3; 1 / 0").unwrap_err(),
+            "division by zero"
+        );
+        assert_eq!(exec(&mut session, "!r").unwrap(), "1");
+        assert_eq!(exec(&mut session, "ref 2").unwrap(), "<ref 1>");
+    }
+
+    #[test]
+    fn count_steps() {
+        let exprs = Arena::new();
+        let mut session = Session::new(&exprs);
+        let expr = parse::expr("1 + 2").into_result().unwrap();
+        let (value, steps) = session.eval_counting_steps(expr).unwrap();
+        assert_eq!(value.to_string(), "3");
+        assert_eq!(steps, 8);
+    }
+}
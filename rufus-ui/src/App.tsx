@@ -19,6 +19,7 @@ type Example = {
 type State = {
   wasm: typeof import('rufus-wasm') | null;
   program: string;
+  prelude: boolean;
   output: string;
   result: string;
   examples: Example[];
@@ -30,6 +31,7 @@ class App extends React.Component<Props, State> {
     this.state = {
       wasm: null,
       program: '',
+      prelude: true,
       output: '',
       result: '',
       examples: [],
@@ -81,13 +83,17 @@ class App extends React.Component<Props, State> {
     this.setState({ program })
   }
 
+  handlePreludeChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    this.setState({ prelude: event.target.checked });
+  }
+
   runCommand = () => {
     const wasm = this.state.wasm;
     if (!wasm) {
       alert("WASM not loaded!");
       return;
     }
-    const result = wasm.exec(this.state.program);
+    const result = wasm.exec(this.state.program, this.state.prelude);
     const status = result.status;
     const value = result.get_value();
     switch (status) {
@@ -163,6 +169,16 @@ class App extends React.Component<Props, State> {
                     </select>
                   </div>
                 </div>
+                <div className="field">
+                  <label className="checkbox">
+                    <input
+                      type="checkbox"
+                      checked={state.prelude}
+                      onChange={this.handlePreludeChange}
+                    />
+                    {' '}Prelude
+                  </label>
+                </div>
               </div>
               <div className="column is-8">
                 <div className="field">
@@ -1,6 +1,9 @@
 use wasm_bindgen::prelude::*;
 
-use rufus_core::{cek, humanizer, parser};
+use rufus_core::diagnostics::Diagnostic;
+use rufus_core::lexer::{self, Lexer};
+use rufus_core::prelude::Prelude;
+use rufus_core::{cek, parse};
 
 #[wasm_bindgen]
 #[derive(Clone, Copy)]
@@ -9,6 +12,20 @@ pub enum ExecResultStatus {
     Err,
 }
 
+/// The classes of tokens reported by `tokenize`.
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub enum TokenClass {
+    Keyword,
+    Identifier,
+    Number,
+    String,
+    Comment,
+    Bracket,
+    Operator,
+    Error,
+}
+
 #[wasm_bindgen]
 #[derive(Clone)]
 pub struct ExecResult {
@@ -23,25 +40,29 @@ impl ExecResult {
     }
 }
 
-fn exec_result(program: &str) -> Result<String, String> {
-    let humanizer = humanizer::Humanizer::new(program);
-    let parser = parser::ExprParser::new();
-    let expr = parser
-        .parse(program)
-        .map_err(|err| {
-            let mut msg = err.map_location(|loc| humanizer.run(loc)).to_string();
-            humanizer::sanitize_source_span(&mut msg);
-            msg
+fn exec_result(program: &str, prelude: bool) -> Result<String, String> {
+    let prelude = if prelude { Some(Prelude::get()) } else { None };
+    let expr = parse::expr(program)
+        .into_result()
+        .map_err(|errors| {
+            let rendered: Vec<_> = errors.iter().map(|err| err.render(program, None)).collect();
+            rendered.join("\n\n")
         })?
-        .index()?;
-    let machine = cek::Machine::new(&expr);
-    let value = machine.run()?;
+        .index_in(prelude.map_or(&[][..], Prelude::names))
+        .map_err(|err| Diagnostic::from(err).render(program, None))?;
+    let (env, store) = prelude.map_or_else(Default::default, Prelude::scope);
+    let mut machine = cek::Machine::new(&expr).with_env(env).with_store(store);
+    let value = machine.run().map_err(|message| {
+        Diagnostic::from_runtime_error(message, machine.error_span()).render(program, None)
+    })?;
     Ok(value.to_string())
 }
 
+/// Run a program, with the bindings of the prelude in scope if `prelude` is
+/// set.
 #[wasm_bindgen]
-pub fn exec(program: &str) -> ExecResult {
-    match exec_result(program) {
+pub fn exec(program: &str, prelude: bool) -> ExecResult {
+    match exec_result(program, prelude) {
         Ok(value) => ExecResult {
             status: ExecResultStatus::Ok,
             value,
@@ -52,3 +73,40 @@ pub fn exec(program: &str) -> ExecResult {
         },
     }
 }
+
+/// Split a program into tokens for syntax highlighting. Every token is
+/// represented by three consecutive numbers: its start and end as UTF-16
+/// offsets, like JavaScript string indices, and its `TokenClass`. Comments are
+/// included, invalid input is reported as `TokenClass::Error`.
+#[wasm_bindgen]
+pub fn tokenize(program: &str) -> Vec<u32> {
+    let mut tokens = Vec::new();
+    // NOTE(MH): Tokens come in order, so we only need to count the UTF-16
+    // code units since the previous offset.
+    let mut last = (0, 0);
+    let mut utf16 = |offset: usize| {
+        let (last_offset, last_utf16) = last;
+        let count = last_utf16 + program[last_offset..offset].encode_utf16().count();
+        last = (offset, count);
+        count as u32
+    };
+    for result in Lexer::new(program).with_comments() {
+        let (start, end, class) = match result {
+            Ok((start, token, end)) => {
+                let class = match token.class() {
+                    lexer::TokenClass::Keyword => TokenClass::Keyword,
+                    lexer::TokenClass::Identifier => TokenClass::Identifier,
+                    lexer::TokenClass::Number => TokenClass::Number,
+                    lexer::TokenClass::String => TokenClass::String,
+                    lexer::TokenClass::Comment => TokenClass::Comment,
+                    lexer::TokenClass::Bracket => TokenClass::Bracket,
+                    lexer::TokenClass::Operator => TokenClass::Operator,
+                };
+                (start, end, class)
+            }
+            Err(err) => (err.span.start, err.span.end, TokenClass::Error),
+        };
+        tokens.extend([utf16(start), utf16(end), class as u32]);
+    }
+    tokens
+}